@@ -7,12 +7,42 @@ pub enum Error {
     /// Some data is corrupted.
     #[error("Corrupted")]
     Corrupted,
+    /// The block at the given file offset is corrupted.
+    #[error("CorruptedBlock at offset {0}")]
+    CorruptedBlock(u64),
     /// Over Memory Limit(cache).
     #[error("MemoryLimit")]
     MemoryLimit,
     /// Put data is too large.
     #[error("TooLargeSize")]
     TooLargeSize,
+    /// The key is larger than `Options::max_key_size`.
+    #[error("KeyTooLarge")]
+    KeyTooLarge,
+    /// The value is larger than `Options::max_value_size`.
+    #[error("ValueTooLarge")]
+    ValueTooLarge,
+    /// The disk is full.
+    #[error("OutOfSpace")]
+    OutOfSpace,
+    /// The path should be a directory, but it is not.
+    #[error("NotDirectory: {0}")]
+    NotDirectory(std::path::PathBuf),
+    /// Some options are invalid.
+    #[error("InvalidArgument")]
+    InvalidArgument,
+    /// Waiting for a lock timed out.
+    #[error("LockTimeout")]
+    LockTimeout,
+    /// A recovered LSN is not less than an LSN already assigned.
+    #[error("LsnRegression: {0}")]
+    LsnRegression(u64),
+    /// Writes are suspended, see `table::suspend::WriteGate`.
+    #[error("Suspended")]
+    Suspended,
+    /// The store is closed.
+    #[error("Closed")]
+    Closed,
 }
 
 // impl From<PageError> for Error {
@@ -3,7 +3,6 @@ use bitflags::bitflags;
 use crate::error::{ Error, Result };
 
 bitflags! {
-    #[derive(Debug)]
     pub struct ChecksumType: u8 {
         const NONE = 0;
         const CRC32 = 1;
@@ -12,6 +11,15 @@ bitflags! {
     }
 }
 
+impl ChecksumType {
+    /// 解码从磁盘读取的校验类型, 只接受支持校验的类型
+    pub(crate) fn decode(bits: u8) -> Result<Self> {
+        match Self::from_bits(bits) {
+            Some(typ @ (Self::NONE | Self::CRC32)) => Ok(typ),
+            _ => Err(Error::Corrupted),
+        }
+    }
+}
 
 pub(crate) fn checksum(typ: ChecksumType, content: &[u8]) -> Option<u32> {
     match typ {
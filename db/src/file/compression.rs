@@ -8,4 +8,93 @@ bitflags! {
         const SNAPPY = 2;
         const ZSTD = 4;
     }
-}
\ No newline at end of file
+}
+
+/// 压缩 block 至少需要节省的比例 (百分比), 否则以不压缩的形式存储
+pub(crate) const DEFAULT_MIN_COMPRESSION_SAVINGS: u8 = 12;
+
+/// 使用 `compression` 压缩 `input`
+pub(crate) fn compress(compression: Compression, input: &[u8]) -> Result<Vec<u8>> {
+    match compression {
+        Compression::NONE => Ok(input.to_vec()),
+        Compression::SNAPPY => snap::raw::Encoder::new()
+            .compress_vec(input)
+            .map_err(|_| Error::Corrupted),
+        Compression::ZSTD => zstd::bulk::compress(input, 0).map_err(|_| Error::Corrupted),
+        _ => Err(Error::Corrupted),
+    }
+}
+
+/// 解压 `compression` 压缩的 `input`
+pub(crate) fn decompress(compression: Compression, input: &[u8]) -> Result<Vec<u8>> {
+    match compression {
+        Compression::NONE => Ok(input.to_vec()),
+        Compression::SNAPPY => snap::raw::Decoder::new()
+            .decompress_vec(input)
+            .map_err(|_| Error::Corrupted),
+        Compression::ZSTD => zstd::stream::decode_all(input).map_err(|_| Error::Corrupted),
+        _ => Err(Error::Corrupted),
+    }
+}
+
+/// 压缩 `input`, 如果压缩没有节省至少 `min_savings`% 的空间, 返回 `None`,
+/// 调用方应当以不压缩的形式存储
+pub(crate) fn compress_if_worthwhile(
+    compression: Compression,
+    input: &[u8],
+    min_savings: u8,
+) -> Result<Option<Vec<u8>>> {
+    if compression == Compression::NONE {
+        return Ok(None);
+    }
+    let output = compress(compression, input)?;
+    let max_len = input.len() - input.len() * min_savings.min(100) as usize / 100;
+    if output.len() < max_len {
+        Ok(Some(output))
+    } else {
+        Ok(None)
+    }
+}
+
+/// 构建文件时 block 压缩的统计
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    /// 以压缩形式存储的 block 数量
+    pub compressed_blocks: u64,
+    /// 因为压缩收益不足而以不压缩形式存储的 block 数量
+    pub skipped_blocks: u64,
+    /// 压缩前的字节数
+    pub raw_bytes: u64,
+    /// 实际存储的字节数
+    pub stored_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+    use super::*;
+
+    #[test]
+    fn compress_roundtrip() {
+        let input = b"hello world ".repeat(100);
+        for compression in [Compression::NONE, Compression::SNAPPY, Compression::ZSTD] {
+            let output = compress(compression, &input).unwrap();
+            assert_eq!(decompress(compression, &output).unwrap(), input);
+        }
+        assert!(decompress(Compression::SNAPPY, b"garbage").is_err());
+    }
+
+    #[test]
+    fn compress_skip_incompressible() {
+        let mut random = vec![0u8; 4096];
+        rand::thread_rng().fill_bytes(&mut random);
+        let compressible = vec![7u8; 4096];
+        for compression in [Compression::SNAPPY, Compression::ZSTD] {
+            assert!(compress_if_worthwhile(compression, &random, 12).unwrap().is_none());
+            assert!(compress_if_worthwhile(compression, &compressible, 12).unwrap().is_some());
+            // Requires saving more than everything.
+            assert!(compress_if_worthwhile(compression, &compressible, 100).unwrap().is_none());
+        }
+        assert!(compress_if_worthwhile(Compression::NONE, &compressible, 0).unwrap().is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+use anyhow::Result;
+use tokio::fs::{remove_file, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use crate::error::Error;
+use crate::file::checksum::ChecksumType;
+use crate::file::constant::{DEFAULT_BLOCK_SIZE, IO_BUFFER_SIZE};
+use crate::file::file_reader::{BlockHandle, FileReader};
+use crate::file::types::{decode_block, FOOTER_LEN};
+
+/// 复制一个 page 文件, 复制时按 block 校验每个 block 的校验和, 返回复制的字节数.
+///
+/// 遇到损坏的 block 时立即失败, 返回 [`Error::CorruptedBlock`] 和 block 在文件中的
+/// 偏移, 并删除已经写入的目标文件, 避免备份中混入损坏的数据.
+pub(crate) async fn verified_copy(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    checksum_type: ChecksumType,
+) -> Result<u64> {
+    let mut reader = FileReader::open(src, false, DEFAULT_BLOCK_SIZE, IO_BUFFER_SIZE).await?;
+    let dst = dst.as_ref();
+    let mut writer = BufWriter::with_capacity(IO_BUFFER_SIZE, File::create(dst).await?);
+    match copy_blocks(&mut reader, &mut writer, checksum_type).await {
+        Ok(size) => Ok(size),
+        Err(err) => {
+            drop(writer);
+            remove_file(dst).await?;
+            Err(err)
+        }
+    }
+}
+
+async fn copy_blocks(
+    reader: &mut FileReader<tokio::io::BufReader<File>>,
+    writer: &mut BufWriter<File>,
+    checksum_type: ChecksumType,
+) -> Result<u64> {
+    let file_size = reader.file_size() as u64;
+    let footer_offset = file_size.saturating_sub(FOOTER_LEN as u64);
+    let corrupted = |offset: u64| Error::CorruptedBlock(offset);
+    let footer = reader
+        .read_footer()
+        .await
+        .map_err(|_| corrupted(footer_offset))?;
+    if footer.checksum_type != checksum_type {
+        return Err(corrupted(footer_offset).into());
+    }
+
+    // 收集文件中所有 block 的位置, 它们必须连续地覆盖 footer 之前的内容
+    let in_file = |handle: &BlockHandle| {
+        handle
+            .offset
+            .checked_add(handle.length)
+            .is_some_and(|end| end <= footer_offset)
+    };
+    if !in_file(&footer.meta_handle) {
+        return Err(corrupted(footer_offset).into());
+    }
+    let meta = reader
+        .read_meta_block(&footer)
+        .await
+        .map_err(|_| corrupted(footer.meta_handle.offset))?;
+    let mut handles = vec![footer.meta_handle];
+    if let Some(handle) = meta.file_meta {
+        if !in_file(&handle) {
+            return Err(corrupted(footer.meta_handle.offset).into());
+        }
+        handles.push(handle);
+    }
+    for index_handle in meta.page_groups.values() {
+        if !in_file(index_handle) {
+            return Err(corrupted(footer.meta_handle.offset).into());
+        }
+        let index = reader
+            .read_index_block(*index_handle, checksum_type)
+            .await
+            .map_err(|_| corrupted(index_handle.offset))?;
+        handles.push(*index_handle);
+        handles.extend(index.meta_page_table);
+        handles.extend(index.page_offsets.values().map(|(handle, _)| *handle));
+    }
+    handles.sort_unstable_by_key(|handle| handle.offset);
+
+    let mut offset = 0;
+    for handle in handles {
+        if handle.offset != offset || !in_file(&handle) {
+            return Err(corrupted(offset).into());
+        }
+        let block = reader.read_block(handle).await?;
+        decode_block(checksum_type, &block).map_err(|_| corrupted(handle.offset))?;
+        writer.write_all(&block).await?;
+        offset += handle.length;
+    }
+    if offset != footer_offset {
+        return Err(corrupted(offset).into());
+    }
+    writer.write_all(&footer.encode()).await?;
+    writer.flush().await?;
+    writer.get_ref().sync_all().await?;
+    Ok(file_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::file_reader::tests::write_test_file;
+    use crate::file::types::BLOCK_TRAILER_LEN;
+
+    #[tokio::test]
+    async fn verified_copy_file() {
+        let dir = tempdir::TempDir::new("verified_copy").unwrap();
+        let src = dir.path().join("1");
+        let dst = dir.path().join("2");
+        let pages = write_test_file(&src).await;
+
+        let size = verified_copy(&src, &dst, ChecksumType::CRC32).await.unwrap();
+        let content = std::fs::read(&src).unwrap();
+        assert_eq!(size, content.len() as u64);
+        assert_eq!(std::fs::read(&dst).unwrap(), content);
+
+        // 校验和类型不一致
+        let err = verified_copy(&src, &dst, ChecksumType::NONE).await.unwrap_err();
+        let footer_offset = (content.len() - FOOTER_LEN) as u64;
+        assert!(matches!(err.downcast_ref(), Some(Error::CorruptedBlock(offset)) if *offset == footer_offset));
+
+        // 破坏第二个 page 的内容
+        let mut corrupted = content.clone();
+        let page_offset = pages[0].1.len() + BLOCK_TRAILER_LEN;
+        corrupted[page_offset + 20] ^= 0xff;
+        std::fs::write(&src, corrupted).unwrap();
+        let err = verified_copy(&src, &dst, ChecksumType::CRC32).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::CorruptedBlock(offset)) if *offset == page_offset as u64));
+        assert!(!dst.exists());
+    }
+}
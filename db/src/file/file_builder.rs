@@ -1,18 +1,68 @@
 use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::Result;
+use rustc_hash::{FxHashMap, FxHashSet};
+use tokio::fs::{remove_file, rename, File};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use crate::error::Error;
 use crate::file::checksum::ChecksumType;
-use crate::file::compression::Compression;
-use crate::page::base::PageInfo;
-use crate::store::Options;
+use crate::file::compression::{
+    compress_if_worthwhile, Compression, CompressionStats, DEFAULT_MIN_COMPRESSION_SAVINGS,
+};
+use crate::file::constant::DEFAULT_BLOCK_SIZE;
+use crate::file::file_reader::BlockHandle;
+use crate::file::open_files::OpenFileLimiter;
+use crate::file::types::{
+    block_trailer, FileMeta, Footer, IndexBlock, MetaBlock, PageGroupMeta, BLOCK_TRAILER_LEN,
+    FOOTER_LEN,
+};
+use crate::page::base::{PageMut, PageRef, PAGE_CONTENT_LEN};
+use crate::page::data::{BlobRef, Key, Value};
+use crate::page::sort::{SortedPageBuilder, SortedPageIter, SortedPageRef};
+use crate::utils::memory::{MemoryController, MemoryReservation, MemoryUsage};
+use crate::utils::rate_limiter::{IoPriority, RateLimiter};
 
-struct IndexBlock {
-    pub(crate) page_offsets: BTreeMap<u64, (u64, PageInfo)>,
-    pub(crate) meta_page_table: Option<u64>,
-}
 #[derive(Default)]
 struct IndexBlockBuilder {
     index_block: IndexBlock,
 }
 
+impl IndexBlockBuilder {
+    fn add_page(&mut self, page_addr: u64, handle: BlockHandle, page: PageRef<'_>) {
+        self.index_block.page_offsets.insert(page_addr, (handle, page.info()));
+    }
+
+    fn set_page_table(&mut self, handle: BlockHandle) {
+        self.index_block.meta_page_table = Some(handle);
+    }
+
+    fn finish(&self) -> Vec<u8> {
+        self.index_block.encode()
+    }
+}
+
+/// 记录 page id 到 page 地址的映射, 写入文件的 page table 块
+#[derive(Default)]
+struct PageTable {
+    table: BTreeMap<u64, u64>,
+}
+
+impl PageTable {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(4 + self.table.len() * 16);
+        buf.extend_from_slice(&(self.table.len() as u32).to_le_bytes());
+        for (id, addr) in &self.table {
+            buf.extend_from_slice(&id.to_le_bytes());
+            buf.extend_from_slice(&addr.to_le_bytes());
+        }
+        buf
+    }
+}
+
+/// 构建一个 page group: 一组连续写入的 page, 以及它们的 page table 和 index 块
 pub(crate) struct CommonFileBuilder {
     group_id: u32,
     compression: Compression,
@@ -22,3 +72,472 @@ pub(crate) struct CommonFileBuilder {
     page_table: PageTable,
 }
 
+impl CommonFileBuilder {
+    pub(crate) fn new(group_id: u32, compression: Compression, checksum: ChecksumType) -> Self {
+        Self {
+            group_id,
+            compression,
+            checksum,
+            index: IndexBlockBuilder::default(),
+            page_table: PageTable::default(),
+        }
+    }
+
+    async fn add_page<W>(
+        &mut self,
+        writer: &mut BlockWriter<W>,
+        page_id: u64,
+        page_addr: u64,
+        page: PageRef<'_>,
+    ) -> Result<()>
+        where
+            W: AsyncWrite + Unpin,
+    {
+        let handle = writer
+            .write_compressed_block(page.data(), self.compression, self.checksum)
+            .await?;
+        self.index.add_page(page_addr, handle, page);
+        self.page_table.table.insert(page_id, page_addr);
+        Ok(())
+    }
+
+    /// 写入 page table 和 index 块, 返回 index 块的位置和内容
+    async fn finish<W>(mut self, writer: &mut BlockWriter<W>) -> Result<(BlockHandle, IndexBlock)>
+        where
+            W: AsyncWrite + Unpin,
+    {
+        let page_table = self.page_table.encode();
+        let handle = writer.write_block(&page_table, Compression::NONE, self.checksum).await?;
+        self.index.set_page_table(handle);
+        let index_block = self.index.finish();
+        let handle = writer.write_block(&index_block, Compression::NONE, self.checksum).await?;
+        Ok((handle, self.index.index_block))
+    }
+}
+
+/// 按 block 格式顺序写入文件, 并记录写入的位置
+struct BlockWriter<W> {
+    writer: W,
+    offset: u64,
+    min_compression_savings: u8,
+    stats: CompressionStats,
+    rate_limiter: Option<(Arc<RateLimiter>, IoPriority)>,
+}
+
+impl<W> BlockWriter<W>
+    where
+        W: AsyncWrite + Unpin,
+{
+    async fn write_block(
+        &mut self,
+        payload: &[u8],
+        compression: Compression,
+        checksum: ChecksumType,
+    ) -> Result<BlockHandle> {
+        let trailer = block_trailer(checksum, compression, payload);
+        if let Some((limiter, priority)) = &self.rate_limiter {
+            let len = payload.len() + trailer.len();
+            limiter.request(*priority, len as u64).await;
+        }
+        self.writer.write_all(payload).await?;
+        self.writer.write_all(&trailer).await?;
+        let handle = BlockHandle {
+            offset: self.offset,
+            length: (payload.len() + trailer.len()) as u64,
+        };
+        self.offset += handle.length;
+        Ok(handle)
+    }
+
+    /// 直接写入已经编码好的 block, 返回写入的起始位置
+    async fn write_raw(&mut self, buf: &[u8]) -> Result<u64> {
+        if let Some((limiter, priority)) = &self.rate_limiter {
+            limiter.request(*priority, buf.len() as u64).await;
+        }
+        self.writer.write_all(buf).await?;
+        let offset = self.offset;
+        self.offset += buf.len() as u64;
+        Ok(offset)
+    }
+
+    /// 压缩并写入 block, 压缩收益不足时以不压缩的形式写入
+    async fn write_compressed_block(
+        &mut self,
+        payload: &[u8],
+        compression: Compression,
+        checksum: ChecksumType,
+    ) -> Result<BlockHandle> {
+        let compressed = compress_if_worthwhile(compression, payload, self.min_compression_savings)?;
+        self.stats.raw_bytes += payload.len() as u64;
+        let handle = match compressed {
+            Some(compressed) => {
+                self.stats.compressed_blocks += 1;
+                self.write_block(&compressed, compression, checksum).await?
+            }
+            None => {
+                if compression != Compression::NONE {
+                    self.stats.skipped_blocks += 1;
+                }
+                self.write_block(payload, Compression::NONE, checksum).await?
+            }
+        };
+        self.stats.stored_bytes += handle.length - BLOCK_TRAILER_LEN as u64;
+        Ok(handle)
+    }
+}
+
+/// File format {
+///     page groups : [page group]*
+///     blob region : [blob block]*, values stored out of line
+///     file meta   : the encoded [`FileMeta`]
+///     meta block  : locates the index block of each page group, the blob region
+///                   and the file meta block
+///     footer      : locates the meta block
+/// }
+///
+/// Page group format {
+///     pages       : [page block]*
+///     page table  : page id -> page addr
+///     index block : page addr -> (page block, page info)
+/// }
+///
+/// 一个 page 的地址由 page group id (高 32 位) 和 group 内的偏移 (低 32 位) 组成,
+/// 连续写入的 page 属于同一个 group.
+///
+/// 大于 `inline_value_threshold` 的 value 先缓存在内存中, 在文件结束时写入 blob
+/// region, page 中只保留指向它们的 [`BlobRef`]. 缓存的大小最多是文件中所有 blob
+/// 的大小, 由 `file_base_size` 限制, 并作为 [`MemoryUsage::Flush`] 计入
+/// [`MemoryController`].
+pub(crate) struct FileBuilder<W> {
+    file_id: u32,
+    writer: BlockWriter<W>,
+    compression: Compression,
+    checksum: ChecksumType,
+    inline_value_threshold: usize,
+    blobs: BlockWriter<Vec<u8>>,
+    memory: Option<Arc<MemoryController>>,
+    blob_memory: Vec<MemoryReservation>,
+    block_size: usize,
+    referenced_groups: FxHashSet<u32>,
+
+    meta: MetaBlock,
+    group_metas: FxHashMap<u32, Arc<PageGroupMeta>>,
+    group: Option<CommonFileBuilder>,
+}
+
+impl FileBuilder<BufWriter<File>> {
+    /// Creates a file at `path`, writes to it through a buffer of
+    /// `io_buffer_size` bytes.
+    pub(crate) async fn create(
+        path: impl AsRef<Path>,
+        file_id: u32,
+        io_buffer_size: usize,
+        compression: Compression,
+        checksum: ChecksumType,
+    ) -> Result<Self> {
+        let file = File::create(path).await?;
+        let writer = BufWriter::with_capacity(io_buffer_size, file);
+        Ok(Self::new(file_id, writer, compression, checksum))
+    }
+}
+
+impl<W> FileBuilder<W>
+    where
+        W: AsyncWrite + Unpin,
+{
+    pub(crate) fn new(
+        file_id: u32,
+        writer: W,
+        compression: Compression,
+        checksum: ChecksumType,
+    ) -> Self {
+        Self {
+            file_id,
+            writer: BlockWriter {
+                writer,
+                offset: 0,
+                min_compression_savings: DEFAULT_MIN_COMPRESSION_SAVINGS,
+                stats: CompressionStats::default(),
+                rate_limiter: None,
+            },
+            compression,
+            checksum,
+            inline_value_threshold: usize::MAX,
+            blobs: BlockWriter {
+                writer: Vec::new(),
+                offset: 0,
+                min_compression_savings: DEFAULT_MIN_COMPRESSION_SAVINGS,
+                stats: CompressionStats::default(),
+                rate_limiter: None,
+            },
+            memory: None,
+            blob_memory: Vec::new(),
+            block_size: DEFAULT_BLOCK_SIZE,
+            referenced_groups: FxHashSet::default(),
+            meta: MetaBlock::default(),
+            group_metas: FxHashMap::default(),
+            group: None,
+        }
+    }
+
+    /// Stores a page uncompressed if compressing it saves less than
+    /// `percent`% of its size.
+    pub(crate) fn with_min_compression_savings(mut self, percent: u8) -> Self {
+        self.writer.min_compression_savings = percent;
+        self.blobs.min_compression_savings = percent;
+        self
+    }
+
+    /// Stores the values larger than `threshold` bytes of leaf data pages in
+    /// the blob region of the file.
+    pub(crate) fn with_inline_value_threshold(mut self, threshold: usize) -> Self {
+        self.inline_value_threshold = threshold;
+        self
+    }
+
+    /// Charges the buffered out-of-line values to `memory`, adding a page
+    /// fails with [`Error::MemoryLimit`] if a strict `memory` is exhausted.
+    pub(crate) fn with_memory_controller(mut self, memory: Arc<MemoryController>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Records the block size the file is read with, 4KB by default.
+    pub(crate) fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Records the files whose pages are referenced by this file, e.g. the
+    /// inputs of the compaction that builds it.
+    pub(crate) fn with_referenced_groups(mut self, groups: impl IntoIterator<Item = u32>) -> Self {
+        self.referenced_groups = groups.into_iter().collect();
+        self
+    }
+
+    /// Limits the write rate of the file with `limiter`.
+    pub(crate) fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>, priority: IoPriority) -> Self {
+        self.writer.rate_limiter = Some((limiter, priority));
+        self
+    }
+
+    /// Returns the compression statistics of the pages added so far.
+    pub(crate) fn compression_stats(&self) -> CompressionStats {
+        self.writer.stats
+    }
+
+    /// Returns true if no page has been added.
+    ///
+    /// An empty file is still valid, but it holds nothing. Compaction whose
+    /// input pages are all dead should skip finishing the file, and only
+    /// delete the input in its version edit.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.group.is_none() && self.meta.page_groups.is_empty()
+    }
+
+    /// Adds a page to the file, pages of the same group must be added
+    /// consecutively.
+    pub(crate) async fn add_page(
+        &mut self,
+        page_id: u64,
+        page_addr: u64,
+        page: PageRef<'_>,
+    ) -> Result<()> {
+        let group_id = (page_addr >> 32) as u32;
+        if self.group.as_ref().map(|g| g.group_id) != Some(group_id) {
+            self.finish_group().await?;
+            assert!(!self.meta.page_groups.contains_key(&group_id));
+            self.group = Some(CommonFileBuilder::new(group_id, self.compression, self.checksum));
+        }
+        if page.tier().is_leaf() && page.kind().is_data() {
+            let sorted = SortedPageRef::<Key<'_>, Value<'_>>::new(page);
+            let mut large_values_size = 0;
+            for (key, value) in SortedPageIter::new(sorted) {
+                self.meta.lsn_range.add(key.lsn);
+                if let Value::Put(v) = value {
+                    if v.len() > self.inline_value_threshold {
+                        large_values_size += v.len() + BLOCK_TRAILER_LEN;
+                    }
+                }
+            }
+            if large_values_size > 0 {
+                // Blocks are stored uncompressed if compression doesn't pay
+                // off, so the blobs take at most this size.
+                if let Some(memory) = &self.memory {
+                    let reservation = memory.reserve(MemoryUsage::Flush, large_values_size as u64)?;
+                    self.blob_memory.push(reservation);
+                }
+                let page = self.move_values_out_of_line(page).await?;
+                let group = self.group.as_mut().unwrap();
+                return group
+                    .add_page(&mut self.writer, page_id, page_addr, PageRef::new(&page))
+                    .await;
+            }
+        }
+        let group = self.group.as_mut().unwrap();
+        group.add_page(&mut self.writer, page_id, page_addr, page).await
+    }
+
+    /// 将超过阈值的 value 写入 blob region, 返回指向它们的 page 副本
+    async fn move_values_out_of_line(&mut self, page: PageRef<'_>) -> Result<Vec<u8>> {
+        let mut items = Vec::new();
+        for (key, value) in SortedPageIter::new(SortedPageRef::<Key<'_>, Value<'_>>::new(page)) {
+            let value = match value {
+                Value::Put(v) if v.len() > self.inline_value_threshold => {
+                    let handle = self
+                        .blobs
+                        .write_compressed_block(v, self.compression, self.checksum)
+                        .await?;
+                    Value::Blob(BlobRef {
+                        offset: handle.offset,
+                        length: handle.length,
+                        size: v.len() as u64,
+                    })
+                }
+                value => value,
+            };
+            items.push((key, value));
+        }
+        let builder = SortedPageBuilder::new(page.tier(), page.kind()).with_slice(&items);
+        let mut buf = vec![0; builder.size()];
+        builder.build(&mut PageMut::new(&mut buf));
+        // 保留原 page 的 epoch 和 chain 信息
+        buf[..PAGE_CONTENT_LEN].copy_from_slice(&page.data()[..PAGE_CONTENT_LEN]);
+        Ok(buf)
+    }
+
+    /// Finishes the file, returns the file size.
+    pub(crate) async fn finish(mut self) -> Result<u64> {
+        self.finish_group().await?;
+        if !self.blobs.writer.is_empty() {
+            let blobs = std::mem::take(&mut self.blobs.writer);
+            self.meta.blob_region = Some(self.writer.write_raw(&blobs).await?);
+            self.blob_memory.clear();
+        }
+        let mut file_meta = FileMeta {
+            file_id: self.file_id,
+            file_size: 0,
+            block_size: self.block_size,
+            referenced_groups: std::mem::take(&mut self.referenced_groups),
+            lsn_range: self.meta.lsn_range,
+            checksum_type: self.checksum,
+            compression: self.compression,
+            page_groups: std::mem::take(&mut self.group_metas),
+        };
+        // The lengths of the file meta block and the meta block don't depend
+        // on the file size, so it is known before they are written.
+        let file_meta_handle = BlockHandle {
+            offset: self.writer.offset,
+            length: (file_meta.encode().len() + BLOCK_TRAILER_LEN) as u64,
+        };
+        self.meta.file_meta = Some(file_meta_handle);
+        let meta_block = self.meta.encode();
+        let file_size = file_meta_handle.offset
+            + file_meta_handle.length
+            + (meta_block.len() + BLOCK_TRAILER_LEN + FOOTER_LEN) as u64;
+        file_meta.file_size = file_size as usize;
+        self.writer
+            .write_block(&file_meta.encode(), Compression::NONE, self.checksum)
+            .await?;
+        let meta_handle = self
+            .writer
+            .write_block(&meta_block, Compression::NONE, self.checksum)
+            .await?;
+        let footer = Footer {
+            meta_handle,
+            checksum_type: self.checksum,
+            compression: self.compression,
+        };
+        let footer = footer.encode();
+        self.writer.writer.write_all(&footer).await?;
+        self.writer.writer.flush().await?;
+        debug_assert_eq!(self.writer.offset + footer.len() as u64, file_size);
+        Ok(file_size)
+    }
+
+    async fn finish_group(&mut self) -> Result<()> {
+        if let Some(group) = self.group.take() {
+            let group_id = group.group_id;
+            let (handle, index) = group.finish(&mut self.writer).await?;
+            let meta = PageGroupMeta::new(self.file_id, group_id, handle, &index);
+            self.meta.page_groups.insert(group_id, handle);
+            self.group_metas.insert(group_id, Arc::new(meta));
+        }
+        Ok(())
+    }
+}
+
+const TEMP_SUFFIX: &str = "tmpdb";
+
+/// Builds a page file at `path` through a temporary file, e.g. for flush.
+///
+/// `open` creates the builder on the temporary file and `build` adds the
+/// pages. The file is synced and renamed to `path` only if it is completely
+/// written, otherwise the temporary file is deleted and the caller keeps its
+/// input to retry. Running out of disk space fails with [`Error::OutOfSpace`].
+///
+/// The directory of `path` is synced too, so the file survives a crash even
+/// if it is not in the manifest directory.
+///
+/// The file counts against `open_files` until it is built.
+pub(crate) async fn build_file<W, F, Fut>(
+    path: impl AsRef<Path>,
+    open_files: &OpenFileLimiter,
+    open: impl FnOnce(File) -> FileBuilder<W>,
+    build: F,
+) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnOnce(FileBuilder<W>) -> Fut,
+        Fut: Future<Output = Result<FileBuilder<W>>>,
+{
+    let path = path.as_ref();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".");
+    tmp_path.push(TEMP_SUFFIX);
+    let tmp_path = PathBuf::from(tmp_path);
+    let _permit = open_files.acquire().await;
+    let result: Result<u64> = async {
+        let builder = build(open(File::create(&tmp_path).await?)).await?;
+        let file_size = builder.finish().await?;
+        File::open(&tmp_path).await?.sync_all().await?;
+        rename(&tmp_path, path).await?;
+        sync_parent_dir(path).await?;
+        Ok(file_size)
+    }
+    .await;
+    let Err(err) = result else {
+        return result;
+    };
+    match remove_file(&tmp_path).await {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    let out_of_space = err.chain().any(|err| {
+        err.downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == ErrorKind::StorageFull)
+    });
+    if out_of_space {
+        Err(err.context(Error::OutOfSpace))
+    } else {
+        Err(err)
+    }
+}
+
+/// Persists the directory entry of `path`.
+#[cfg(unix)]
+async fn sync_parent_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir).await?.sync_all().await?;
+    Ok(())
+}
+
+/// Directories can't be opened as files on Windows, the entries are persisted
+/// by the filesystem there.
+#[cfg(not(unix))]
+async fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
@@ -1,8 +1,18 @@
 use std::alloc::Layout;
 use std::io::SeekFrom;
+use std::path::Path;
+use crate::error::Error;
+use crate::file::checksum::ChecksumType;
+use crate::file::compression::{decompress, Compression};
+use crate::file::open_files::{OpenFileLimiter, OpenFilePermit};
+use crate::file::retry::RetryPolicy;
+use crate::file::types::{decode_block, FileMeta, Footer, IndexBlock, MetaBlock, FOOTER_LEN};
+use crate::page::base::PageRef;
+use crate::page::data::BlobRef;
 use crate::utils::atomic::Count;
 use anyhow::Result;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader};
 
 
 #[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
@@ -12,7 +22,7 @@ pub(crate) struct BlockHandle {
 }
 
 
-struct FileReader<R> where
+pub(crate) struct FileReader<R> where
     R: AsyncSeekExt+  AsyncRead + Unpin{
     reader: R,
     use_direct: bool,
@@ -21,6 +31,38 @@ struct FileReader<R> where
     file_size: usize,
     // 文件大小
     read_bytes: Count, // 已经读取的字节大小
+    pos: Option<u64>, // reader 当前的位置, 顺序读取时不需要 seek
+    retry: RetryPolicy,
+    // 文件打开期间持有, 限制同时打开的文件数
+    open_file: Option<OpenFilePermit>,
+}
+
+impl FileReader<BufReader<File>> {
+    /// 打开文件, 通过 `io_buffer_size` 大小的缓冲区读取
+    pub(crate) async fn open(
+        path: impl AsRef<Path>,
+        use_direct: bool,
+        align_size: usize,
+        io_buffer_size: usize,
+    ) -> Result<Self> {
+        let file = File::open(path).await?;
+        let file_size = file.metadata().await?.len() as usize;
+        let reader = BufReader::with_capacity(io_buffer_size, file);
+        Ok(Self::from(reader, use_direct, align_size, file_size))
+    }
+
+    /// 等待 `limiter` 允许后再打开文件, 文件打开数达到上限时排队而不是失败
+    pub(crate) async fn open_limited(
+        limiter: &OpenFileLimiter,
+        path: impl AsRef<Path>,
+        use_direct: bool,
+        align_size: usize,
+        io_buffer_size: usize,
+    ) -> Result<Self> {
+        let permit = limiter.acquire().await;
+        let reader = Self::open(path, use_direct, align_size, io_buffer_size).await?;
+        Ok(reader.with_open_file_permit(permit))
+    }
 }
 
 impl<R> FileReader<R> where R: AsyncSeekExt+  AsyncRead + Unpin  {
@@ -36,33 +78,335 @@ impl<R> FileReader<R> where R: AsyncSeekExt+  AsyncRead + Unpin  {
             align_size,
             file_size,
             read_bytes: Count::default(),
+            pos: None,
+            retry: RetryPolicy::default(),
+            open_file: None,
         }
     }
 
+    /// 持有 `permit` 直到读取器被释放
+    pub(crate) fn with_open_file_permit(mut self, permit: OpenFilePermit) -> Self {
+        self.open_file = Some(permit);
+        self
+    }
+
+    /// 读取遇到暂时性的错误时按照 `retry` 重试
+    pub(crate) fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
     /// 从指定偏移量的页面上准确读取指定数量的字节。
     pub async fn read_exact_at(&mut self, buf: &mut [u8], req_offset: u64) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.read_exact_at_once(buf, req_offset).await {
+                Err(err) if self.retry.should_retry(attempt, &err) => {
+                    self.retry.backoff(attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn read_exact_at_once(&mut self, buf: &mut [u8], req_offset: u64) -> Result<()> {
         if buf.is_empty() {
             return Ok(());
         };
 
         if !self.use_direct {
-            self.reader.seek(SeekFrom::Start(req_offset)).await?;
+            self.seek_to(req_offset).await?;
             self.reader.read_exact(buf).await?;
+            self.pos = Some(req_offset + buf.len() as u64);
+            self.read_bytes.add(buf.len() as u64);
         };
 
         Ok(())
     }
 
+    /// 从指定偏移量读取最多 `buf.len()` 个字节, 返回实际读取的字节数。
+    ///
+    /// 与 [`Self::read_exact_at`] 不同, 读到文件末尾时不会返回错误, 而是返回
+    /// 一个较小的字节数。
+    pub async fn read_at(&mut self, buf: &mut [u8], req_offset: u64) -> Result<usize> {
+        let mut attempt = 0;
+        loop {
+            match self.read_at_once(buf, req_offset).await {
+                Err(err) if self.retry.should_retry(attempt, &err) => {
+                    self.retry.backoff(attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn read_at_once(&mut self, buf: &mut [u8], req_offset: u64) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.seek_to(req_offset).await?;
+        let mut read = 0;
+        while read < buf.len() {
+            let n = self.reader.read(&mut buf[read..]).await?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        self.pos = Some(req_offset + read as u64);
+        self.read_bytes.add(read as u64);
+        Ok(read)
+    }
+
+    /// 移动到 `offset`, 读取失败时 reader 的位置是未知的, 下次读取需要重新 seek
+    async fn seek_to(&mut self, offset: u64) -> Result<()> {
+        if self.pos.take() != Some(offset) {
+            self.reader.seek(SeekFrom::Start(offset)).await?;
+        }
+        Ok(())
+    }
+
     pub async fn read_block(&mut self, block_handle: BlockHandle) -> Result<Vec<u8>> {
         let mut buf = vec![0u8; block_handle.length as usize];
         self.read_exact_at(&mut buf, block_handle.offset).await?;
         Ok(buf)
     }
 
+    /// 读取 block 并校验尾部, 返回 block 的 payload
+    pub(crate) async fn read_checked_block(
+        &mut self,
+        block_handle: BlockHandle,
+        checksum_type: ChecksumType,
+    ) -> Result<Vec<u8>> {
+        let block = self.read_block(block_handle).await?;
+        checked_payload(checksum_type, block)
+    }
+
+    /// 读取文件末尾的 footer
+    pub(crate) async fn read_footer(&mut self) -> Result<Footer> {
+        if self.file_size < FOOTER_LEN {
+            return Err(Error::Corrupted.into());
+        }
+        let mut buf = [0u8; FOOTER_LEN];
+        self.read_exact_at(&mut buf, (self.file_size - FOOTER_LEN) as u64).await?;
+        Ok(Footer::decode(&buf)?)
+    }
+
+    pub(crate) async fn read_meta_block(&mut self, footer: &Footer) -> Result<MetaBlock> {
+        let buf = self.read_checked_block(footer.meta_handle, footer.checksum_type).await?;
+        Ok(MetaBlock::decode(&buf)?)
+    }
+
+    /// Rebuilds the [`FileMeta`] of the file from its file meta block.
+    ///
+    /// The file is corrupted if the file meta doesn't match its footer or
+    /// its size.
+    pub(crate) async fn read_file_meta(&mut self) -> Result<FileMeta> {
+        let footer = self.read_footer().await?;
+        let meta = self.read_meta_block(&footer).await?;
+        let Some(handle) = meta.file_meta else {
+            return Err(Error::Corrupted.into());
+        };
+        let buf = self.read_checked_block(handle, footer.checksum_type).await?;
+        let file_meta = FileMeta::decode(&buf)?;
+        if file_meta.file_size != self.file_size
+            || file_meta.checksum_type != footer.checksum_type
+            || file_meta.compression != footer.compression
+            || file_meta.lsn_range != meta.lsn_range
+            || file_meta.page_groups.len() != meta.page_groups.len()
+        {
+            return Err(Error::Corrupted.into());
+        }
+        Ok(file_meta)
+    }
+
+    /// 读取 blob region 中 `blob` 指向的 value
+    pub(crate) async fn read_blob(
+        &mut self,
+        meta: &MetaBlock,
+        blob: BlobRef,
+        checksum_type: ChecksumType,
+    ) -> Result<Vec<u8>> {
+        let Some(blob_region) = meta.blob_region else {
+            return Err(Error::Corrupted.into());
+        };
+        let handle = BlockHandle {
+            offset: blob_region + blob.offset,
+            length: blob.length,
+        };
+        let value = self.read_checked_block(handle, checksum_type).await?;
+        if value.len() as u64 != blob.size {
+            return Err(Error::Corrupted.into());
+        }
+        Ok(value)
+    }
+
+    pub(crate) async fn read_index_block(
+        &mut self,
+        block_handle: BlockHandle,
+        checksum_type: ChecksumType,
+    ) -> Result<IndexBlock> {
+        let buf = self.read_checked_block(block_handle, checksum_type).await?;
+        Ok(IndexBlock::decode(&buf)?)
+    }
+
+    /// 按照在文件中的偏移顺序, 将每个 page 的地址和内容交给 `f`, 每次只读取一个 page.
+    ///
+    /// 校验失败的 page 不会交给 `f`, 而是继续处理后面的 page, 最后返回这些 page 的地址.
+    pub(crate) async fn for_each_page<F>(&mut self, mut f: F) -> Result<Vec<u64>>
+        where
+            F: FnMut(u64, PageRef<'_>),
+    {
+        let footer = self.read_footer().await?;
+        let meta = self.read_meta_block(&footer).await?;
+        let mut pages = Vec::new();
+        for index_handle in meta.page_groups.values() {
+            let index = self.read_index_block(*index_handle, footer.checksum_type).await?;
+            pages.extend(
+                index
+                    .page_offsets
+                    .into_iter()
+                    .map(|(page_addr, (handle, _))| (handle, page_addr)),
+            );
+        }
+        pages.sort_unstable_by_key(|(handle, _)| handle.offset);
+
+        let mut corrupted = Vec::new();
+        for (handle, page_addr) in pages {
+            let block = self.read_block(handle).await?;
+            match checked_payload(footer.checksum_type, block) {
+                Ok(page) => f(page_addr, PageRef::new(&page)),
+                Err(_) => corrupted.push(page_addr),
+            }
+        }
+        Ok(corrupted)
+    }
+
     #[inline]
     pub(crate) fn total_read_bytes(&self) -> u64 {
         self.read_bytes.get()
     }
+
+    #[inline]
+    pub(crate) fn file_size(&self) -> usize {
+        self.file_size
+    }
+}
+
+/// 使用 `std::fs` 的同步文件读取器, 绕过 tokio, 适用于单线程的使用者
+pub(crate) struct SyncFileReader {
+    file: std::fs::File,
+    read_bytes: Count,
+    // 文件打开期间持有, 限制同时打开的文件数
+    open_file: Option<OpenFilePermit>,
+}
+
+impl SyncFileReader {
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(Self {
+            file,
+            read_bytes: Count::default(),
+            open_file: None,
+        })
+    }
+
+    /// 持有 `permit` 直到读取器被释放
+    pub(crate) fn with_open_file_permit(mut self, permit: OpenFilePermit) -> Self {
+        self.open_file = Some(permit);
+        self
+    }
+
+    pub(crate) fn read_block_sync(&self, block_handle: BlockHandle) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; block_handle.length as usize];
+        read_exact_at(&self.file, &mut buf, block_handle.offset)?;
+        self.read_bytes.add(buf.len() as u64);
+        Ok(buf)
+    }
+
+    /// 读取文件末尾的 footer
+    pub(crate) fn read_footer_sync(&self) -> Result<Footer> {
+        let file_size = self.file.metadata()?.len() as usize;
+        if file_size < FOOTER_LEN {
+            return Err(Error::Corrupted.into());
+        }
+        let mut buf = [0u8; FOOTER_LEN];
+        read_exact_at(&self.file, &mut buf, (file_size - FOOTER_LEN) as u64)?;
+        self.read_bytes.add(FOOTER_LEN as u64);
+        Ok(Footer::decode(&buf)?)
+    }
+
+    /// 读取 block 并校验尾部, 返回 block 的 payload
+    pub(crate) fn read_checked_block_sync(
+        &self,
+        block_handle: BlockHandle,
+        checksum_type: ChecksumType,
+    ) -> Result<Vec<u8>> {
+        let block = self.read_block_sync(block_handle)?;
+        checked_payload(checksum_type, block)
+    }
+
+    #[inline]
+    pub(crate) fn total_read_bytes(&self) -> u64 {
+        self.read_bytes.get()
+    }
+}
+
+/// 从 `offset` 处准确读取 `buf.len()` 个字节, 不依赖也不改变文件的读写位置
+#[cfg(unix)]
+fn read_exact_at(file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::os::unix::fs::FileExt;
+
+    file.read_exact_at(buf, offset)
+}
+
+/// 从 `offset` 处准确读取 `buf.len()` 个字节
+///
+/// `seek_read` 会移动文件的读写位置, 但 [`SyncFileReader`] 的读取都指定了偏移量,
+/// 所以不受影响
+#[cfg(windows)]
+fn read_exact_at(file: &std::fs::File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind};
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            Ok(n) => {
+                let tmp = buf;
+                buf = &mut tmp[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// 从 `offset` 处准确读取 `buf.len()` 个字节
+///
+/// 没有定位读取的平台上先 seek 再读取, 并发的读取需要调用方串行化
+#[cfg(not(any(unix, windows)))]
+fn read_exact_at(mut file: &std::fs::File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    use std::io::{Read, Seek};
+
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+/// 校验并解压 block, 返回 payload
+fn checked_payload(checksum_type: ChecksumType, mut block: Vec<u8>) -> Result<Vec<u8>> {
+    let (compression, payload) = decode_block(checksum_type, &block)?;
+    if compression != Compression::NONE {
+        return Ok(decompress(compression, payload)?);
+    }
+    let len = payload.len();
+    block.truncate(len);
+    Ok(block)
 }
 
 #[inline]
@@ -130,8 +474,721 @@ unsafe impl Sync for AlignBuffer {}
 
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncSeek, AsyncWrite, ReadBuf};
+    use crate::file::file_builder::FileBuilder;
+    use crate::file::types::{LsnRange, BLOCK_TRAILER_LEN};
+    use crate::page::base::tests::alloc_page;
+    use crate::page::base::{PageKind, PageMut, PageTier};
+    use crate::page::data::{Key, Value};
+    use crate::page::sort::SortedPageBuilder;
+
+    pub(crate) fn build_page(key: &[u8], lsn: u64, value: &[u8]) -> Box<[u8]> {
+        let item = (Key::new(key, lsn), Value::Put(value));
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_item(item);
+        let mut buf = alloc_page(builder.size());
+        builder.build(&mut PageMut::new(&mut buf));
+        buf
+    }
+
+    /// 写入一个包含两个 page group 的文件, 返回每个 page 的地址和内容
+    pub(crate) async fn write_test_file(path: &std::path::Path) -> Vec<(u64, Box<[u8]>)> {
+        let pages = vec![
+            ((1 << 32) | 8, build_page(b"a", 7, b"1")),
+            ((1 << 32) | 64, build_page(b"b", 3, b"22")),
+            (2 << 32, build_page(b"c", 5, b"333")),
+        ];
+        let file = File::create(path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::NONE, ChecksumType::CRC32);
+        for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+            builder
+                .add_page(page_id as u64, *page_addr, PageRef::new(page))
+                .await
+                .unwrap();
+        }
+        builder.finish().await.unwrap();
+        pages
+    }
+
+    async fn open_reader(path: &std::path::Path) -> FileReader<File> {
+        let file = File::open(path).await.unwrap();
+        let file_size = file.metadata().await.unwrap().len() as usize;
+        FileReader::from(file, false, 4096, file_size)
+    }
+
+    #[tokio::test]
+    async fn test_for_each_page() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let pages = write_test_file(&path).await;
+
+        let mut reader = open_reader(&path).await;
+        let mut visited = Vec::new();
+        let corrupted = reader
+            .for_each_page(|page_addr, page| visited.push((page_addr, page.data().to_vec())))
+            .await
+            .unwrap();
+        assert!(corrupted.is_empty());
+        let expect: Vec<_> = pages.iter().map(|(a, p)| (*a, p.to_vec())).collect();
+        assert_eq!(visited, expect);
+
+        // 破坏第二个 page 的内容
+        let mut content = std::fs::read(&path).unwrap();
+        content[pages[0].1.len() + BLOCK_TRAILER_LEN + 20] ^= 0xff;
+        std::fs::write(&path, content).unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let mut visited = Vec::new();
+        let corrupted = reader
+            .for_each_page(|page_addr, _| visited.push(page_addr))
+            .await
+            .unwrap();
+        assert_eq!(corrupted, vec![pages[1].0]);
+        assert_eq!(visited, vec![pages[0].0, pages[2].0]);
+    }
+
+    #[tokio::test]
+    async fn test_read_file_meta() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(3, file, Compression::NONE, ChecksumType::CRC32)
+            .with_referenced_groups([1, 2]);
+        builder.add_page(1, (1 << 32) | 8, PageRef::new(&build_page(b"a", 7, b"1"))).await.unwrap();
+        builder.add_page(2, 2 << 32, PageRef::new(&build_page(b"b", 3, b"2"))).await.unwrap();
+        let file_size = builder.finish().await.unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), file_size);
+
+        let mut reader = open_reader(&path).await;
+        let meta = reader.read_file_meta().await.unwrap();
+        assert_eq!(meta.file_id, 3);
+        assert_eq!(meta.file_size as u64, file_size);
+        assert_eq!(meta.block_size, 4096);
+        assert_eq!(meta.referenced_groups, [1, 2].into_iter().collect());
+        assert_eq!(meta.lsn_range, LsnRange::new(3, 7));
+        assert_eq!(meta.checksum_type, ChecksumType::CRC32);
+        let mut groups: Vec<_> = meta.page_groups.keys().copied().collect();
+        groups.sort_unstable();
+        assert_eq!(groups, vec![1, 2]);
+
+        // A truncated file is corrupted even if its footer is intact.
+        let mut content = std::fs::read(&path).unwrap();
+        content.insert(0, 0);
+        std::fs::write(&path, content).unwrap();
+        let mut reader = open_reader(&path).await;
+        let err = reader.read_file_meta().await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Corrupted)));
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_checksum_type() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        write_test_file(&path).await;
+        let content = std::fs::read(&path).unwrap();
+        let checksum_pos = content.len() - FOOTER_LEN + 16;
+
+        // Unsupported checksum types are reported as corruption.
+        for bits in [2, 3, 0xff] {
+            let mut content = content.clone();
+            content[checksum_pos] = bits;
+            std::fs::write(&path, content).unwrap();
+            let mut reader = open_reader(&path).await;
+            let err = reader.for_each_page(|_, _| {}).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Corrupted)));
+            assert!(SyncFileReader::open(&path).unwrap().read_footer_sync().is_err());
+        }
+    }
+
+    /// 统计底层读写调用次数的文件
+    struct CountingFile {
+        file: File,
+        calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl CountingFile {
+        fn count(&self) {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    impl AsyncRead for CountingFile {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.count();
+            Pin::new(&mut self.file).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncSeek for CountingFile {
+        fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+            Pin::new(&mut self.file).start_seek(position)
+        }
+
+        fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+            Pin::new(&mut self.file).poll_complete(cx)
+        }
+    }
+
+    impl AsyncWrite for CountingFile {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.count();
+            Pin::new(&mut self.file).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.file).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.file).poll_shutdown(cx)
+        }
+    }
+
+    /// 写入 `capacity` 字节之后返回 ENOSPC 的文件, 模拟磁盘已满
+    struct FullDisk {
+        file: File,
+        capacity: usize,
+    }
+
+    impl AsyncWrite for FullDisk {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            if self.capacity == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::StorageFull.into()));
+            }
+            let len = buf.len().min(self.capacity);
+            let poll = Pin::new(&mut self.file).poll_write(cx, &buf[..len]);
+            if let Poll::Ready(Ok(n)) = poll {
+                self.capacity -= n;
+            }
+            poll
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.file).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.file).poll_shutdown(cx)
+        }
+    }
+
+    /// 前 `failures` 次读取返回 `kind` 错误的 reader
+    struct FlakyReader {
+        inner: std::io::Cursor<Vec<u8>>,
+        failures: usize,
+        kind: std::io::ErrorKind,
+        reads: usize,
+    }
+
+    impl AsyncRead for FlakyReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.reads += 1;
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Poll::Ready(Err(self.kind.into()));
+            }
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncSeek for FlakyReader {
+        fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+            Pin::new(&mut self.inner).start_seek(position)
+        }
+
+        fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+            Pin::new(&mut self.inner).poll_complete(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_retry() {
+        use std::time::Duration;
+        use crate::file::retry::RetryPolicy;
+
+        let data: Vec<u8> = (0..=255).collect();
+        let flaky_reader = |failures, kind, max_retries| {
+            let reader = FlakyReader {
+                inner: std::io::Cursor::new(data.clone()),
+                failures,
+                kind,
+                reads: 0,
+            };
+            let retry = RetryPolicy::new(max_retries).with_backoff(Duration::ZERO, Duration::ZERO);
+            FileReader::from(reader, false, 4096, data.len()).with_retry(retry)
+        };
+        let mut buf = [0u8; 16];
+
+        // Succeeds within the retry budget.
+        let mut reader = flaky_reader(3, std::io::ErrorKind::Interrupted, 3);
+        reader.read_exact_at(&mut buf, 100).await.unwrap();
+        assert_eq!(buf.as_slice(), &data[100..116]);
+        assert_eq!(reader.read_at(&mut buf, 250).await.unwrap(), 6);
+        assert_eq!(&buf[..6], &data[250..]);
+
+        // Surfaces the original error after the retries are exhausted.
+        let mut reader = flaky_reader(4, std::io::ErrorKind::TimedOut, 3);
+        let err = reader.read_exact_at(&mut buf, 0).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<std::io::Error>().unwrap().kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(reader.reader.reads, 4);
+        // The reader is still usable.
+        let mut reader = flaky_reader(1, std::io::ErrorKind::WouldBlock, 0);
+        assert!(reader.read_at(&mut buf, 0).await.is_err());
+        assert_eq!(reader.read_at(&mut buf, 0).await.unwrap(), 16);
+        assert_eq!(buf.as_slice(), &data[..16]);
+
+        // Other errors are never retried.
+        let mut reader = flaky_reader(1, std::io::ErrorKind::PermissionDenied, 3);
+        assert!(reader.read_exact_at(&mut buf, 0).await.is_err());
+        assert_eq!(reader.reader.reads, 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_files_limit() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use crate::file::open_files::OpenFileLimiter;
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let pages = write_test_file(&path).await;
+
+        let limiter = Arc::new(OpenFileLimiter::new(2));
+        let open = Arc::new(AtomicUsize::new(0));
+        let max_open = Arc::new(AtomicUsize::new(0));
+        let mut tasks = Vec::new();
+        for _ in 0..16 {
+            let (limiter, open, max_open) = (limiter.clone(), open.clone(), max_open.clone());
+            let path = path.clone();
+            let (page_addr, page) = pages[0].clone();
+            tasks.push(tokio::spawn(async move {
+                let mut reader = FileReader::open_limited(&limiter, &path, false, 4096, 4096)
+                    .await
+                    .unwrap();
+                let n = open.fetch_add(1, Ordering::SeqCst) + 1;
+                max_open.fetch_max(n, Ordering::SeqCst);
+                let mut read = Vec::new();
+                reader
+                    .for_each_page(|addr, p| {
+                        if addr == page_addr {
+                            read = p.data().to_vec();
+                        }
+                    })
+                    .await
+                    .unwrap();
+                assert_eq!(read.as_slice(), &page[..]);
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                open.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+        // Opens beyond the limit wait instead of failing.
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert!(max_open.load(Ordering::SeqCst) <= 2);
+        assert_eq!(limiter.available(), Some(2));
+        assert_eq!(OpenFileLimiter::new(0).available(), None);
+    }
+
+    #[tokio::test]
+    async fn test_build_file_out_of_space() {
+        use crate::file::file_builder::build_file;
+        use crate::file::open_files::OpenFileLimiter;
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let pages = [
+            ((1 << 32) | 8, build_page(b"a", 1, &[1u8; 1024])),
+            ((1 << 32) | 64, build_page(b"b", 2, &[2u8; 1024])),
+        ];
+        let add_pages = |mut builder: FileBuilder<_>| async {
+            for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+                builder.add_page(page_id as u64, *page_addr, PageRef::new(page)).await?;
+            }
+            Ok(builder)
+        };
+
+        let open = |capacity| {
+            move |file| {
+                let writer = FullDisk { file, capacity };
+                FileBuilder::new(1, writer, Compression::NONE, ChecksumType::CRC32)
+            }
+        };
+        let open_files = OpenFileLimiter::new(1);
+        let err = build_file(&path, &open_files, open(1500), add_pages).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::OutOfSpace)), "{err:?}");
+        // No partial file is left.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // The pages are still there, retry after space frees up.
+        let file_size = build_file(&path, &open_files, open(usize::MAX), add_pages).await.unwrap();
+        assert_eq!(open_files.available(), Some(1));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), file_size);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let mut reader = open_reader(&path).await;
+        let mut visited = Vec::new();
+        reader
+            .for_each_page(|page_addr, page| visited.push((page_addr, page.data().to_vec())))
+            .await
+            .unwrap();
+        let expect: Vec<_> = pages.iter().map(|(a, p)| (*a, p.to_vec())).collect();
+        assert_eq!(visited, expect);
+    }
+
+    /// 使用 `io_buffer_size` 写入并读取文件, 返回读到的 page 和底层调用次数
+    async fn write_and_read(
+        path: &std::path::Path,
+        pages: &[(u64, Box<[u8]>)],
+        io_buffer_size: usize,
+    ) -> (Vec<(u64, Vec<u8>)>, usize, usize) {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let file = CountingFile {
+            file: File::create(path).await.unwrap(),
+            calls: calls.clone(),
+        };
+        let writer = tokio::io::BufWriter::with_capacity(io_buffer_size, file);
+        let mut builder = FileBuilder::new(1, writer, Compression::NONE, ChecksumType::CRC32);
+        for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+            builder
+                .add_page(page_id as u64, *page_addr, PageRef::new(page))
+                .await
+                .unwrap();
+        }
+        let file_size = builder.finish().await.unwrap() as usize;
+        let writes = calls.swap(0, std::sync::atomic::Ordering::Relaxed);
+
+        let file = CountingFile {
+            file: File::open(path).await.unwrap(),
+            calls: calls.clone(),
+        };
+        let reader = BufReader::with_capacity(io_buffer_size, file);
+        let mut reader = FileReader::from(reader, false, 4096, file_size);
+        let mut visited = Vec::new();
+        let corrupted = reader
+            .for_each_page(|page_addr, page| visited.push((page_addr, page.data().to_vec())))
+            .await
+            .unwrap();
+        assert!(corrupted.is_empty());
+        let reads = calls.load(std::sync::atomic::Ordering::Relaxed);
+        (visited, writes, reads)
+    }
+
+    #[tokio::test]
+    async fn test_io_buffer_size() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let pages: Vec<_> = (0..64u64)
+            .map(|i| ((1 << 32) | (i * 64), build_page(&i.to_be_bytes(), i, &[i as u8; 100])))
+            .collect();
+        let expect: Vec<_> = pages.iter().map(|(a, p)| (*a, p.to_vec())).collect();
+
+        let (small, small_writes, small_reads) =
+            write_and_read(&dir.path().join("1"), &pages, 16).await;
+        let (large, large_writes, large_reads) =
+            write_and_read(&dir.path().join("2"), &pages, 1 << 20).await;
+        assert_eq!(small, expect);
+        assert_eq!(large, expect);
+        assert!(large_writes < small_writes);
+        assert!(large_reads < small_reads);
+    }
+
+    #[tokio::test]
+    async fn test_compression_skip() {
+        use rand::RngCore;
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let mut random = vec![0u8; 4096];
+        rand::thread_rng().fill_bytes(&mut random);
+        let pages = [
+            ((1 << 32) | 8, build_page(b"a", 1, &random)),
+            ((1 << 32) | 64, build_page(b"b", 2, &[7u8; 4096])),
+        ];
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::ZSTD, ChecksumType::CRC32)
+            .with_min_compression_savings(12);
+        for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+            builder
+                .add_page(page_id as u64, *page_addr, PageRef::new(page))
+                .await
+                .unwrap();
+        }
+        let stats = builder.compression_stats();
+        assert_eq!(stats.compressed_blocks, 1);
+        assert_eq!(stats.skipped_blocks, 1);
+        assert!(stats.stored_bytes < stats.raw_bytes);
+        builder.finish().await.unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let footer = reader.read_footer().await.unwrap();
+        let meta = reader.read_meta_block(&footer).await.unwrap();
+        let index_handle = meta.page_groups[&1];
+        let index = reader.read_index_block(index_handle, footer.checksum_type).await.unwrap();
+        let mut tags = Vec::new();
+        for (handle, _) in index.page_offsets.values() {
+            let block = reader.read_block(*handle).await.unwrap();
+            tags.push(decode_block(footer.checksum_type, &block).unwrap().0);
+        }
+        assert_eq!(tags, vec![Compression::NONE, Compression::ZSTD]);
+
+        let mut visited = Vec::new();
+        reader
+            .for_each_page(|page_addr, page| visited.push((page_addr, page.data().to_vec())))
+            .await
+            .unwrap();
+        let expect: Vec<_> = pages.iter().map(|(a, p)| (*a, p.to_vec())).collect();
+        assert_eq!(visited, expect);
+    }
+
+    #[tokio::test]
+    async fn test_empty_file() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let file = File::create(&path).await.unwrap();
+        let builder = FileBuilder::new(1, file, Compression::NONE, ChecksumType::CRC32);
+        assert!(builder.is_empty());
+        builder.finish().await.unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let mut visited = 0;
+        let corrupted = reader.for_each_page(|_, _| visited += 1).await.unwrap();
+        assert!(corrupted.is_empty());
+        assert_eq!(visited, 0);
+
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::NONE, ChecksumType::CRC32);
+        builder
+            .add_page(0, 1 << 32, PageRef::new(&build_page(b"a", 1, b"1")))
+            .await
+            .unwrap();
+        assert!(!builder.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_builder() {
+        use std::sync::Arc;
+        use crate::file::types::FOOTER_LEN;
+        use crate::utils::rate_limiter::{IoPriority, RateLimiter};
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let limiter = Arc::new(RateLimiter::new(1 << 30));
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::NONE, ChecksumType::CRC32)
+            .with_rate_limiter(limiter.clone(), IoPriority::Low);
+        builder
+            .add_page(0, 1 << 32, PageRef::new(&build_page(b"a", 1, b"1")))
+            .await
+            .unwrap();
+        let file_size = builder.finish().await.unwrap();
+        // Every block except the footer is charged.
+        assert_eq!(limiter.stats().total_bytes, file_size - FOOTER_LEN as u64);
+    }
+
+    #[tokio::test]
+    async fn test_blob_memory() {
+        use std::sync::Arc;
+        use crate::utils::memory::MemoryController;
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let large = vec![7u8; 4096];
+        let memory = Arc::new(MemoryController::new(6000, true));
+        let file = File::create(dir.path().join("1")).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::NONE, ChecksumType::CRC32)
+            .with_inline_value_threshold(64)
+            .with_memory_controller(memory.clone());
+        let page = build_page(b"a", 1, &large);
+        builder.add_page(1, 1 << 32, PageRef::new(&page)).await.unwrap();
+        // The buffered blob is charged until the file is finished.
+        let charged = (large.len() + BLOCK_TRAILER_LEN) as u64;
+        assert_eq!(memory.stats().flush, charged);
+        let page = build_page(b"b", 2, &large);
+        let err = builder.add_page(2, (1 << 32) | 64, PageRef::new(&page)).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::MemoryLimit)));
+        // Small values are not charged.
+        let page = build_page(b"c", 3, b"small");
+        builder.add_page(3, (1 << 32) | 128, PageRef::new(&page)).await.unwrap();
+        assert_eq!(memory.used(), charged);
+        builder.finish().await.unwrap();
+        assert_eq!(memory.used(), 0);
+        assert_eq!(memory.stats().flush, 0);
+    }
+
+    #[tokio::test]
+    async fn test_out_of_line_values() {
+        use crate::page::sort::{SortedPageIter, SortedPageRef};
+
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let large = vec![7u8; 4096];
+        let items = [
+            (Key::new(b"a", 3), Value::Put(large.as_slice())),
+            (Key::new(b"a", 2), Value::Delete),
+            (Key::new(b"b", 1), Value::Put(b"small".as_slice())),
+        ];
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items);
+        let mut page = alloc_page(builder.size());
+        builder.build(&mut PageMut::new(&mut page));
+        PageMut::new(&mut page).set_epoch(9);
+        let pages = [
+            ((1 << 32) | 8, page),
+            ((1 << 32) | 64, build_page(b"c", 4, &large)),
+        ];
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::SNAPPY, ChecksumType::CRC32)
+            .with_inline_value_threshold(64);
+        for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+            builder
+                .add_page(page_id as u64, *page_addr, PageRef::new(page))
+                .await
+                .unwrap();
+        }
+        builder.finish().await.unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let footer = reader.read_footer().await.unwrap();
+        let meta = reader.read_meta_block(&footer).await.unwrap();
+        assert_eq!(meta.lsn_range, LsnRange::new(1, 4));
+        let blob_region = meta.blob_region.unwrap();
+        let mut visited = Vec::new();
+        reader
+            .for_each_page(|_, page| visited.push(page.data().to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(visited.len(), 2);
+
+        let mut values = Vec::new();
+        for page in &visited {
+            let page = PageRef::new(page);
+            // The pages stay small, only the pointers are stored inline.
+            assert!(page.size() < 128);
+            for (key, value) in SortedPageIter::new(SortedPageRef::<Key<'_>, Value<'_>>::new(page)) {
+                let value = match value {
+                    Value::Put(v) => Some(v.to_vec()),
+                    Value::Delete => None,
+                    Value::Blob(blob) => {
+                        assert!(blob.offset + blob.length <= footer.meta_handle.offset - blob_region);
+                        Some(reader.read_blob(&meta, blob, footer.checksum_type).await.unwrap())
+                    }
+                };
+                values.push((key.raw.to_vec(), key.lsn, value));
+            }
+        }
+        assert_eq!(PageRef::new(&visited[0]).epoch(), 9);
+        assert_eq!(
+            values,
+            vec![
+                (b"a".to_vec(), 3, Some(large.clone())),
+                (b"a".to_vec(), 2, None),
+                (b"b".to_vec(), 1, Some(b"small".to_vec())),
+                (b"c".to_vec(), 4, Some(large.clone())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_block_sync() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let mut random = vec![0u8; 1024];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut random);
+        let pages = [
+            ((1 << 32) | 8, build_page(b"a", 1, &random)),
+            ((1 << 32) | 64, build_page(b"b", 2, &[7u8; 4096])),
+        ];
+        let file = File::create(&path).await.unwrap();
+        let mut builder = FileBuilder::new(1, file, Compression::SNAPPY, ChecksumType::CRC32);
+        for (page_id, (page_addr, page)) in pages.iter().enumerate() {
+            builder
+                .add_page(page_id as u64, *page_addr, PageRef::new(page))
+                .await
+                .unwrap();
+        }
+        builder.finish().await.unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let sync_reader = SyncFileReader::open(&path).unwrap();
+        let footer = reader.read_footer().await.unwrap();
+        let meta = reader.read_meta_block(&footer).await.unwrap();
+        let index_handle = meta.page_groups[&1];
+        let index = reader.read_index_block(index_handle, footer.checksum_type).await.unwrap();
+        let mut handles = vec![footer.meta_handle, index_handle];
+        handles.extend(index.page_offsets.values().map(|(handle, _)| *handle));
+        for handle in handles {
+            assert_eq!(
+                sync_reader.read_block_sync(handle).unwrap(),
+                reader.read_block(handle).await.unwrap()
+            );
+            assert_eq!(
+                sync_reader.read_checked_block_sync(handle, footer.checksum_type).unwrap(),
+                reader.read_checked_block(handle, footer.checksum_type).await.unwrap()
+            );
+        }
+        assert!(sync_reader.total_read_bytes() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_at() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        let content: Vec<u8> = (0..100u8).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let mut reader = open_reader(&path).await;
+        let mut buf = [0u8; 30];
+        assert_eq!(reader.read_at(&mut buf, 10).await.unwrap(), 30);
+        assert_eq!(buf.as_slice(), &content[10..40]);
+        assert_eq!(reader.read_at(&mut buf, 90).await.unwrap(), 10);
+        assert_eq!(&buf[..10], &content[90..]);
+        assert_eq!(reader.read_at(&mut buf, 200).await.unwrap(), 0);
+        assert!(reader.read_exact_at(&mut buf, 90).await.is_err());
+        // The reader recovers after a failed read.
+        assert_eq!(reader.read_at(&mut buf, 0).await.unwrap(), 30);
+        assert_eq!(buf.as_slice(), &content[..30]);
+        assert_eq!(reader.total_read_bytes(), 70);
+    }
+
+    #[tokio::test]
+    async fn test_lsn_range() {
+        let dir = tempdir::TempDir::new("file_reader").unwrap();
+        let path = dir.path().join("1");
+        write_test_file(&path).await;
+
+        let mut reader = open_reader(&path).await;
+        let footer = reader.read_footer().await.unwrap();
+        let meta = reader.read_meta_block(&footer).await.unwrap();
+        let lsn_range = meta.lsn_range;
+        assert_eq!(lsn_range, LsnRange::new(3, 7));
+
+        // A version at lsn 7 is found in a newer source.
+        assert!(!lsn_range.may_contain_newer(7));
+        assert!(lsn_range.may_contain_newer(6));
+        // Snapshot reads at lsn 2 skip the file.
+        assert!(!lsn_range.may_contain_visible(2));
+        assert!(lsn_range.may_contain_visible(3));
+
+        let empty = LsnRange::default();
+        assert!(!empty.may_contain_newer(0));
+        assert!(!empty.may_contain_visible(u64::MAX));
+    }
 
     #[test]
     fn test_floor_to_block_lo_pos() {
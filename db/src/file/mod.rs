@@ -1,8 +1,12 @@
-mod file_reader;
-mod types;
-mod checksum;
-mod compression;
+pub(crate) mod file_reader;
+pub(crate) mod types;
+pub(crate) mod checksum;
+pub(crate) mod compression;
 mod file_builder;
+pub(crate) mod copy;
+pub(crate) mod page_iter;
+pub(crate) mod retry;
+pub(crate) mod open_files;
 
 pub(crate) mod constant {
     pub(crate) const DEFAULT_BLOCK_SIZE: usize = 4096;
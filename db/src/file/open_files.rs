@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Bounds the number of page files open at the same time, so that bursty
+/// flushes and compactions wait for a file to be closed instead of failing
+/// with EMFILE.
+///
+/// A permit is acquired before a file is opened and released when the file
+/// is closed: readers and page iterators hold it until they are dropped, and
+/// [`build_file`] holds it until the file is built.
+///
+/// [`build_file`]: crate::file::file_builder::build_file
+pub(crate) struct OpenFileLimiter {
+    /// `None` if unlimited.
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl OpenFileLimiter {
+    /// Creates a limiter of `max_open_files` files, zero means unlimited.
+    pub(crate) fn new(max_open_files: usize) -> Self {
+        let semaphore = (max_open_files > 0).then(|| Arc::new(Semaphore::new(max_open_files)));
+        Self { semaphore }
+    }
+
+    /// Waits until a file can be opened.
+    pub(crate) async fn acquire(&self) -> OpenFilePermit {
+        let permit = match &self.semaphore {
+            // The semaphore is never closed.
+            Some(semaphore) => Some(semaphore.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        OpenFilePermit(permit)
+    }
+
+    /// Returns the number of files that can be opened without waiting, or
+    /// `None` if unlimited.
+    pub(crate) fn available(&self) -> Option<usize> {
+        self.semaphore.as_ref().map(|s| s.available_permits())
+    }
+}
+
+impl Default for OpenFileLimiter {
+    /// Unlimited.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// A permit to keep a file open, released when dropped.
+pub(crate) struct OpenFilePermit(#[allow(dead_code)] Option<OwnedSemaphorePermit>);
@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::file::checksum::ChecksumType;
+use crate::file::file_reader::{BlockHandle, SyncFileReader};
+use crate::file::open_files::OpenFilePermit;
+use crate::file::types::{IndexBlock, MetaBlock};
+use crate::page::iter::{RewindableIterator, SeekableIterator};
+
+/// 按照 index 的顺序 (page 地址递增) 逐个读取文件中的 page, 每次只读取一个 page,
+/// 不会把整个文件加载到内存中.
+///
+/// Page 文件中的 page 属于不同的节点, index 中只有 page 地址而没有 key, 所以只能按照
+/// page 地址 seek. 读取或者校验失败会作为迭代的元素返回, 之后可以继续迭代后面的 page.
+pub(crate) struct FilePageIter {
+    reader: SyncFileReader,
+    checksum_type: ChecksumType,
+    /// (page addr, page block), 按照 page addr 排序
+    pages: Vec<(u64, BlockHandle)>,
+    next: usize,
+}
+
+impl FilePageIter {
+    /// Opens the file at `path`, reads its meta and index blocks.
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = SyncFileReader::open(path)?;
+        let footer = reader.read_footer_sync()?;
+        let checksum_type = footer.checksum_type;
+        let meta = reader.read_checked_block_sync(footer.meta_handle, checksum_type)?;
+        let meta = MetaBlock::decode(&meta)?;
+        let mut pages = Vec::new();
+        for index_handle in meta.page_groups.values() {
+            let index = reader.read_checked_block_sync(*index_handle, checksum_type)?;
+            let index = IndexBlock::decode(&index)?;
+            pages.extend(
+                index
+                    .page_offsets
+                    .into_iter()
+                    .map(|(page_addr, (handle, _))| (page_addr, handle)),
+            );
+        }
+        pages.sort_unstable_by_key(|(page_addr, _)| *page_addr);
+        Ok(Self {
+            reader,
+            checksum_type,
+            pages,
+            next: 0,
+        })
+    }
+
+    /// Holds `permit` until the iterator is dropped.
+    pub(crate) fn with_open_file_permit(mut self, permit: OpenFilePermit) -> Self {
+        self.reader = self.reader.with_open_file_permit(permit);
+        self
+    }
+
+    /// Returns the number of pages in the file.
+    pub(crate) fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the address and the block length of the next page.
+    pub(crate) fn peek(&self) -> Option<(u64, u64)> {
+        self.pages.get(self.next).map(|(page_addr, handle)| (*page_addr, handle.length))
+    }
+}
+
+impl Iterator for FilePageIter {
+    /// (page addr, page)
+    type Item = Result<(u64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (page_addr, handle) = *self.pages.get(self.next)?;
+        self.next += 1;
+        Some(
+            self.reader
+                .read_checked_block_sync(handle, self.checksum_type)
+                .map(|page| (page_addr, page)),
+        )
+    }
+}
+
+impl RewindableIterator for FilePageIter {
+    fn rewind(&mut self) {
+        self.next = 0;
+    }
+}
+
+/// Seeks to a page address.
+impl SeekableIterator<u64> for FilePageIter {
+    fn seek(&mut self, target: &u64) -> bool {
+        match self.pages.binary_search_by_key(target, |(page_addr, _)| *page_addr) {
+            Ok(i) => {
+                self.next = i;
+                true
+            }
+            Err(i) => {
+                self.next = i;
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::file_reader::tests::write_test_file;
+
+    #[tokio::test]
+    async fn file_page_iter() {
+        let dir = tempdir::TempDir::new("page_iter").unwrap();
+        let path = dir.path().join("1");
+        let pages = write_test_file(&path).await;
+        let expect: Vec<_> = pages.iter().map(|(a, p)| (*a, p.to_vec())).collect();
+
+        let mut iter = FilePageIter::open(&path).unwrap();
+        assert_eq!(iter.len(), 3);
+        let items: Vec<_> = iter.by_ref().map(Result::unwrap).collect();
+        assert_eq!(items, expect);
+        assert!(iter.next().is_none());
+
+        iter.rewind();
+        assert_eq!(iter.next().unwrap().unwrap(), expect[0]);
+
+        // Seek across the page group boundary.
+        assert!(iter.seek(&(2 << 32)));
+        assert_eq!(iter.next().unwrap().unwrap(), expect[2]);
+        assert!(!iter.seek(&((1 << 32) | 9)));
+        let items: Vec<_> = iter.map(Result::unwrap).collect();
+        assert_eq!(items, expect[1..]);
+    }
+
+    #[tokio::test]
+    async fn file_page_iter_corrupted() {
+        let dir = tempdir::TempDir::new("page_iter").unwrap();
+        let path = dir.path().join("1");
+        let pages = write_test_file(&path).await;
+        // Corrupts the first page.
+        let mut content = std::fs::read(&path).unwrap();
+        content[20] ^= 0xff;
+        std::fs::write(&path, content).unwrap();
+
+        let iter = FilePageIter::open(&path).unwrap();
+        let items: Vec<_> = iter.collect();
+        assert_eq!(items.len(), 3);
+        assert!(items[0].is_err());
+        assert_eq!(items[1].as_ref().unwrap().0, pages[1].0);
+        assert_eq!(items[2].as_ref().unwrap().0, pages[2].0);
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(1);
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// When to retry a failed IO operation.
+///
+/// Only transient errors are retried, e.g. the ones network filesystems
+/// return occasionally. The backoff doubles after each attempt. After the
+/// retries are exhausted the original error is returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Never retries.
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: INITIAL_BACKOFF,
+            max_backoff: MAX_BACKOFF,
+        }
+    }
+
+    pub(crate) fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns true if the `attempt`-th retry (starting from 0) of the
+    /// operation that failed with `err` is allowed.
+    pub(crate) fn should_retry(&self, attempt: u32, err: &anyhow::Error) -> bool {
+        attempt < self.max_retries && is_transient(err)
+    }
+
+    /// Waits before the `attempt`-th retry.
+    pub(crate) async fn backoff(&self, attempt: u32) {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_backoff);
+        if !backoff.is_zero() {
+            tokio::time::sleep(backoff).await;
+        }
+    }
+}
+
+/// Returns true if `err` is caused by a transient IO error.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        err.downcast_ref::<std::io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+            )
+        })
+    })
+}
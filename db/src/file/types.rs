@@ -1,13 +1,319 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use rustc_hash::{FxHashMap, FxHashSet};
+use crate::error::{Error, Result};
+use crate::file::checksum::{check_checksum, checksum, ChecksumType};
+use crate::file::compression::Compression;
+use crate::file::constant::FILE_MAGIC;
+use crate::file::file_reader::BlockHandle;
 use crate::page::base::PageInfo;
+use crate::utils::bitmap::FixedBitmap;
 
+/// Block format {
+///     payload     : multiple bytes
+///     compression : 1 bytes  payload 使用的压缩算法
+///     checksum    : 4 bytes  payload 和 compression 的校验和
+/// }
+pub(crate) const BLOCK_TRAILER_LEN: usize = 5;
+
+/// Footer format {
+///     meta_offset   : 8 bytes
+///     meta_length   : 8 bytes
+///     checksum_type : 1 bytes
+///     compression   : 1 bytes
+///     magic         : 8 bytes
+/// }
+pub(crate) const FOOTER_LEN: usize = 26;
+
+/// 生成 block 的尾部
+pub(crate) fn block_trailer(
+    checksum_type: ChecksumType,
+    compression: Compression,
+    payload: &[u8],
+) -> [u8; BLOCK_TRAILER_LEN] {
+    let mut trailer = [0u8; BLOCK_TRAILER_LEN];
+    trailer[0] = compression.bits();
+    let mut content = Vec::with_capacity(payload.len() + 1);
+    content.extend_from_slice(payload);
+    content.push(trailer[0]);
+    let checksum_val = checksum(checksum_type, &content).unwrap_or(0);
+    trailer[1..].copy_from_slice(&checksum_val.to_le_bytes());
+    trailer
+}
+
+/// 校验 block 的尾部, 返回 payload 以及 payload 使用的压缩算法
+pub(crate) fn decode_block(
+    checksum_type: ChecksumType,
+    block: &[u8],
+) -> Result<(Compression, &[u8])> {
+    if block.len() < BLOCK_TRAILER_LEN {
+        return Err(Error::Corrupted);
+    }
+    let (content, checksum_bytes) = block.split_at(block.len() - BLOCK_TRAILER_LEN + 1);
+    let checksum_val = u32::from_le_bytes(checksum_bytes.try_into().unwrap());
+    check_checksum(checksum_type, content, checksum_val)?;
+    let (payload, tag) = content.split_at(content.len() - 1);
+    let compression = Compression::from_bits(tag[0]).ok_or(Error::Corrupted)?;
+    Ok((compression, payload))
+}
+
+/// A little-endian decoder over untrusted bytes read from a file.
+pub(crate) struct BlockDecoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BlockDecoder<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub(crate) fn get_slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(Error::Corrupted);
+        }
+        let (v, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(v)
+    }
+
+    pub(crate) fn get_u8(&mut self) -> Result<u8> {
+        Ok(self.get_slice(1)?[0])
+    }
+
+    pub(crate) fn get_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.get_slice(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn get_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.get_slice(8)?.try_into().unwrap()))
+    }
+}
+
+/// The footer of a file, locates the meta block.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Footer {
+    pub(crate) meta_handle: BlockHandle,
+    pub(crate) checksum_type: ChecksumType,
+    pub(crate) compression: Compression,
+}
+
+impl Footer {
+    pub(crate) fn encode(&self) -> [u8; FOOTER_LEN] {
+        let mut buf = [0u8; FOOTER_LEN];
+        buf[0..8].copy_from_slice(&self.meta_handle.offset.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.meta_handle.length.to_le_bytes());
+        buf[16] = self.checksum_type.bits();
+        buf[17] = self.compression.bits();
+        buf[18..].copy_from_slice(&FILE_MAGIC.to_le_bytes());
+        buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        let mut dec = BlockDecoder::new(buf);
+        let offset = dec.get_u64()?;
+        let length = dec.get_u64()?;
+        let checksum_type = ChecksumType::decode(dec.get_u8()?)?;
+        let compression = Compression::from_bits(dec.get_u8()?).ok_or(Error::Corrupted)?;
+        if dec.get_u64()? != FILE_MAGIC {
+            return Err(Error::Corrupted);
+        }
+        Ok(Self {
+            meta_handle: BlockHandle { offset, length },
+            checksum_type,
+            compression,
+        })
+    }
+}
+
+/// The range of lsn of the keys in a file, used to skip files that can't
+/// contain the wanted versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct LsnRange {
+    pub(crate) min: u64,
+    pub(crate) max: u64,
+}
+
+impl LsnRange {
+    pub(crate) const fn new(min: u64, max: u64) -> Self {
+        Self { min, max }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    pub(crate) fn add(&mut self, lsn: u64) {
+        self.min = self.min.min(lsn);
+        self.max = self.max.max(lsn);
+    }
+
+    /// Returns true if the file may contain a version newer than `lsn`.
+    ///
+    /// Once a version at `lsn` is found in a newer source, files without
+    /// newer versions can be skipped.
+    pub(crate) fn may_contain_newer(&self, lsn: u64) -> bool {
+        !self.is_empty() && self.max > lsn
+    }
+
+    /// Returns true if the file may contain a version visible to a snapshot
+    /// at `lsn`.
+    pub(crate) fn may_contain_visible(&self, lsn: u64) -> bool {
+        !self.is_empty() && self.min <= lsn
+    }
+}
+
+impl Default for LsnRange {
+    /// Returns an empty range.
+    fn default() -> Self {
+        Self::new(u64::MAX, 0)
+    }
+}
+
+/// The meta block of a file, locates the index block of each page group.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub(crate) struct MetaBlock {
+    /// group id -> index block
+    pub(crate) page_groups: BTreeMap<u32, BlockHandle>,
+    pub(crate) lsn_range: LsnRange,
+    /// The offset of the blob region, if the file has out-of-line values.
+    pub(crate) blob_region: Option<u64>,
+    /// The file meta block, see [`FileMeta`].
+    pub(crate) file_meta: Option<BlockHandle>,
+}
+
+/// The tags of the optional fields of a meta block.
+const META_BLOB_REGION: u8 = 1;
+const META_FILE_META: u8 = 2;
+
+impl MetaBlock {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(20 + self.page_groups.len() * 20);
+        buf.extend_from_slice(&self.lsn_range.min.to_le_bytes());
+        buf.extend_from_slice(&self.lsn_range.max.to_le_bytes());
+        buf.extend_from_slice(&(self.page_groups.len() as u32).to_le_bytes());
+        for (group_id, handle) in &self.page_groups {
+            buf.extend_from_slice(&group_id.to_le_bytes());
+            buf.extend_from_slice(&handle.offset.to_le_bytes());
+            buf.extend_from_slice(&handle.length.to_le_bytes());
+        }
+        // Optional fields are appended as [tag, value]* only if present.
+        if let Some(offset) = self.blob_region {
+            buf.push(META_BLOB_REGION);
+            buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        if let Some(handle) = self.file_meta {
+            buf.push(META_FILE_META);
+            buf.extend_from_slice(&handle.offset.to_le_bytes());
+            buf.extend_from_slice(&handle.length.to_le_bytes());
+        }
+        buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        let mut dec = BlockDecoder::new(buf);
+        let lsn_range = LsnRange::new(dec.get_u64()?, dec.get_u64()?);
+        let num_groups = dec.get_u32()?;
+        let mut page_groups = BTreeMap::new();
+        for _ in 0..num_groups {
+            let group_id = dec.get_u32()?;
+            let handle = BlockHandle {
+                offset: dec.get_u64()?,
+                length: dec.get_u64()?,
+            };
+            page_groups.insert(group_id, handle);
+        }
+        let mut blob_region = None;
+        let mut file_meta = None;
+        while !dec.is_empty() {
+            match dec.get_u8()? {
+                META_BLOB_REGION if blob_region.is_none() => {
+                    blob_region = Some(dec.get_u64()?);
+                }
+                META_FILE_META if file_meta.is_none() => {
+                    file_meta = Some(BlockHandle {
+                        offset: dec.get_u64()?,
+                        length: dec.get_u64()?,
+                    });
+                }
+                _ => return Err(Error::Corrupted),
+            }
+        }
+        Ok(Self {
+            page_groups,
+            lsn_range,
+            blob_region,
+            file_meta,
+        })
+    }
+}
+
+/// The index block of a page group, locates each page of the group.
+#[derive(Default)]
+pub(crate) struct IndexBlock {
+    /// page addr -> (page block, page info)
+    pub(crate) page_offsets: BTreeMap<u64, (BlockHandle, PageInfo)>,
+    pub(crate) meta_page_table: Option<BlockHandle>,
+}
+
+impl IndexBlock {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(20 + self.page_offsets.len() * 48);
+        let table = self.meta_page_table.unwrap_or_default();
+        buf.extend_from_slice(&table.offset.to_le_bytes());
+        buf.extend_from_slice(&table.length.to_le_bytes());
+        buf.extend_from_slice(&(self.page_offsets.len() as u32).to_le_bytes());
+        for (addr, (handle, info)) in &self.page_offsets {
+            let (meta, next) = info.value();
+            buf.extend_from_slice(&addr.to_le_bytes());
+            buf.extend_from_slice(&handle.offset.to_le_bytes());
+            buf.extend_from_slice(&handle.length.to_le_bytes());
+            buf.extend_from_slice(&meta.to_le_bytes());
+            buf.extend_from_slice(&next.to_le_bytes());
+            buf.extend_from_slice(&(info.size() as u64).to_le_bytes());
+        }
+        buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        let mut dec = BlockDecoder::new(buf);
+        let table = BlockHandle {
+            offset: dec.get_u64()?,
+            length: dec.get_u64()?,
+        };
+        let num_pages = dec.get_u32()?;
+        let mut page_offsets = BTreeMap::new();
+        for _ in 0..num_pages {
+            let addr = dec.get_u64()?;
+            let handle = BlockHandle {
+                offset: dec.get_u64()?,
+                length: dec.get_u64()?,
+            };
+            let meta = dec.get_u64()?;
+            let next = dec.get_u64()?;
+            let size = dec.get_u64()? as usize;
+            page_offsets.insert(addr, (handle, PageInfo::from_raw(meta, next, size)));
+        }
+        if !dec.is_empty() {
+            return Err(Error::Corrupted);
+        }
+        Ok(Self {
+            page_offsets,
+            meta_page_table: (table.length > 0).then_some(table),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub(crate) struct PageHandle {
     pub(crate) offset: u32,
     pub(crate) size: u32
 }
 
+#[derive(Debug, PartialEq, Eq)]
 struct PageMeta {
     index: u32,
     info: PageInfo,
@@ -27,6 +333,7 @@ pub(crate) struct PageGroupIterator {
     active_pages: Vec<(u32, u32)>
 }
 
+#[derive(Debug, PartialEq, Eq)]
 pub(crate) struct PageGroupMeta {
     pub(crate) group_id: u32,
     pub(crate) file_id: u32,
@@ -36,21 +343,282 @@ pub(crate) struct PageGroupMeta {
     page_meta_map: FxHashMap<u32,PageMeta>
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) struct FileMeta {
     pub(crate) file_id: u32,
     pub(crate) file_size: usize,
     pub(crate) block_size: usize,
     pub(crate) referenced_groups: FxHashSet<u32>,
+    pub(crate) lsn_range: LsnRange,
 
     pub(crate) checksum_type: ChecksumType,
     pub(crate) compression: Compression,
     pub(crate) page_groups: FxHashMap<u32, Arc<PageGroupMeta>>
 }
 
+/// The meta of a file, stored in the file meta block before the meta block
+/// and rebuilt by [`FileReader::read_file_meta`].
+///
+/// [`FileReader::read_file_meta`]: crate::file::file_reader::FileReader::read_file_meta
+///
+/// FileMeta format {
+///     version           : 1 bytes
+///     file_id           : 4 bytes
+///     file_size         : 8 bytes
+///     block_size        : 8 bytes
+///     checksum_type     : 1 bytes
+///     compression       : 1 bytes
+///     lsn_range         : 16 bytes
+///     referenced_groups : count (4 bytes) + [group id (4 bytes)]*
+///     page_groups       : count (4 bytes) + [page group meta]*
+/// }
+///
+/// Page group meta format {
+///     group_id, file_id                              : 4 bytes each
+///     base_offset, page_table_offset, meta_block_end : 8 bytes each
+///     pages : count (4 bytes) + [index, info (meta, next, size), handle (offset, size)]*
+/// }
+///
+/// 集合按 id 排序写入, 保证编码结果是确定的
+impl FileMeta {
+    const VERSION: u8 = 1;
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.push(Self::VERSION);
+        buf.extend_from_slice(&self.file_id.to_le_bytes());
+        buf.extend_from_slice(&(self.file_size as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.block_size as u64).to_le_bytes());
+        buf.push(self.checksum_type.bits());
+        buf.push(self.compression.bits());
+        buf.extend_from_slice(&self.lsn_range.min.to_le_bytes());
+        buf.extend_from_slice(&self.lsn_range.max.to_le_bytes());
+
+        let mut referenced_groups: Vec<_> = self.referenced_groups.iter().copied().collect();
+        referenced_groups.sort_unstable();
+        buf.extend_from_slice(&(referenced_groups.len() as u32).to_le_bytes());
+        for group_id in referenced_groups {
+            buf.extend_from_slice(&group_id.to_le_bytes());
+        }
+
+        let page_groups: BTreeMap<_, _> = self.page_groups.iter().collect();
+        buf.extend_from_slice(&(page_groups.len() as u32).to_le_bytes());
+        for group in page_groups.values() {
+            group.encode_to(&mut buf);
+        }
+        buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self> {
+        let mut dec = BlockDecoder::new(buf);
+        if dec.get_u8()? != Self::VERSION {
+            return Err(Error::Corrupted);
+        }
+        let file_id = dec.get_u32()?;
+        let file_size = dec.get_u64()? as usize;
+        let block_size = dec.get_u64()? as usize;
+        let checksum_type = ChecksumType::decode(dec.get_u8()?)?;
+        let compression = Compression::from_bits(dec.get_u8()?).ok_or(Error::Corrupted)?;
+        let lsn_range = LsnRange::new(dec.get_u64()?, dec.get_u64()?);
+
+        let mut referenced_groups = FxHashSet::default();
+        for _ in 0..dec.get_u32()? {
+            referenced_groups.insert(dec.get_u32()?);
+        }
+        let mut page_groups = FxHashMap::default();
+        for _ in 0..dec.get_u32()? {
+            let group = PageGroupMeta::decode_from(&mut dec)?;
+            page_groups.insert(group.group_id, Arc::new(group));
+        }
+        if !dec.is_empty() {
+            return Err(Error::Corrupted);
+        }
+        Ok(Self {
+            file_id,
+            file_size,
+            block_size,
+            referenced_groups,
+            lsn_range,
+            checksum_type,
+            compression,
+            page_groups,
+        })
+    }
+}
+
+impl PageGroupMeta {
+    /// Builds the meta of a page group from its index block at `index_handle`.
+    ///
+    /// Pages are keyed by the low 32 bits of their addresses, and located
+    /// relative to the first page of the group.
+    pub(crate) fn new(file_id: u32, group_id: u32, index_handle: BlockHandle, index: &IndexBlock) -> Self {
+        let page_table_offset = index.meta_page_table.unwrap_or(index_handle).offset;
+        let base_offset = index
+            .page_offsets
+            .values()
+            .map(|(handle, _)| handle.offset)
+            .min()
+            .unwrap_or(page_table_offset);
+        let mut page_meta_map = FxHashMap::default();
+        for (i, (addr, (handle, info))) in index.page_offsets.iter().enumerate() {
+            let meta = PageMeta {
+                index: i as u32,
+                info: *info,
+                handle: PageHandle {
+                    offset: (handle.offset - base_offset) as u32,
+                    size: handle.length as u32,
+                },
+            };
+            page_meta_map.insert(*addr as u32, meta);
+        }
+        Self {
+            group_id,
+            file_id,
+            base_offset,
+            page_table_offset,
+            meta_block_end: index_handle.offset + index_handle.length,
+            page_meta_map,
+        }
+    }
+
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.group_id.to_le_bytes());
+        buf.extend_from_slice(&self.file_id.to_le_bytes());
+        buf.extend_from_slice(&self.base_offset.to_le_bytes());
+        buf.extend_from_slice(&self.page_table_offset.to_le_bytes());
+        buf.extend_from_slice(&self.meta_block_end.to_le_bytes());
+        let pages: BTreeMap<_, _> = self.page_meta_map.iter().collect();
+        buf.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        for (index, page) in pages {
+            let (meta, next) = page.info.value();
+            buf.extend_from_slice(&index.to_le_bytes());
+            buf.extend_from_slice(&page.index.to_le_bytes());
+            buf.extend_from_slice(&meta.to_le_bytes());
+            buf.extend_from_slice(&next.to_le_bytes());
+            buf.extend_from_slice(&(page.info.size() as u64).to_le_bytes());
+            buf.extend_from_slice(&page.handle.offset.to_le_bytes());
+            buf.extend_from_slice(&page.handle.size.to_le_bytes());
+        }
+    }
+
+    fn decode_from(dec: &mut BlockDecoder<'_>) -> Result<Self> {
+        let group_id = dec.get_u32()?;
+        let file_id = dec.get_u32()?;
+        let base_offset = dec.get_u64()?;
+        let page_table_offset = dec.get_u64()?;
+        let meta_block_end = dec.get_u64()?;
+        let mut page_meta_map = FxHashMap::default();
+        for _ in 0..dec.get_u32()? {
+            let key = dec.get_u32()?;
+            let index = dec.get_u32()?;
+            let meta = dec.get_u64()?;
+            let next = dec.get_u64()?;
+            let size = dec.get_u64()? as usize;
+            let handle = PageHandle {
+                offset: dec.get_u32()?,
+                size: dec.get_u32()?,
+            };
+            let info = PageInfo::from_raw(meta, next, size);
+            page_meta_map.insert(key, PageMeta { index, info, handle });
+        }
+        Ok(Self {
+            group_id,
+            file_id,
+            base_offset,
+            page_table_offset,
+            meta_block_end,
+            page_meta_map,
+        })
+    }
+}
+
 pub(crate) struct FileInfo {
     up1: u32,
     up2: u32,
 
     meta: Arc<FileMeta>
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_group_meta(group_id: u32, num_pages: u32) -> PageGroupMeta {
+        let mut page_meta_map = FxHashMap::default();
+        for i in 0..num_pages {
+            let meta = PageMeta {
+                index: i,
+                info: PageInfo::from_raw(i as u64 + 1, (group_id as u64) << 32, 64),
+                handle: PageHandle {
+                    offset: i * 69,
+                    size: 69,
+                },
+            };
+            page_meta_map.insert(i * 64, meta);
+        }
+        PageGroupMeta {
+            group_id,
+            file_id: 7,
+            base_offset: group_id as u64 * 4096,
+            page_table_offset: group_id as u64 * 4096 + 1024,
+            meta_block_end: group_id as u64 * 4096 + 2048,
+            page_meta_map,
+        }
+    }
+
+    #[test]
+    fn meta_block_optional_fields() {
+        let mut meta = MetaBlock {
+            lsn_range: LsnRange::new(1, 9),
+            ..Default::default()
+        };
+        meta.page_groups.insert(1, BlockHandle { offset: 0, length: 64 });
+        let old = meta.encode();
+        assert_eq!(MetaBlock::decode(&old).unwrap(), meta);
+        meta.blob_region = Some(64);
+        meta.file_meta = Some(BlockHandle { offset: 128, length: 32 });
+        let buf = meta.encode();
+        assert_eq!(MetaBlock::decode(&buf).unwrap(), meta);
+
+        // Unknown tags and duplicated fields are rejected.
+        let mut unknown = old.clone();
+        unknown.push(3);
+        assert!(MetaBlock::decode(&unknown).is_err());
+        let mut duplicated = buf;
+        duplicated.push(META_BLOB_REGION);
+        duplicated.extend_from_slice(&64u64.to_le_bytes());
+        assert!(MetaBlock::decode(&duplicated).is_err());
+    }
+
+    #[test]
+    fn file_meta_roundtrip() {
+        let mut page_groups = FxHashMap::default();
+        for (group_id, num_pages) in [(1, 3), (2, 0), (5, 10)] {
+            page_groups.insert(group_id, Arc::new(page_group_meta(group_id, num_pages)));
+        }
+        let meta = FileMeta {
+            file_id: 7,
+            file_size: 1 << 20,
+            block_size: 4096,
+            referenced_groups: [3, 4, 9].into_iter().collect(),
+            lsn_range: LsnRange::new(10, 20),
+            checksum_type: ChecksumType::CRC32,
+            compression: Compression::ZSTD,
+            page_groups,
+        };
+        let buf = meta.encode();
+        assert_eq!(FileMeta::decode(&buf).unwrap(), meta);
+
+        // Truncated or trailing bytes, and unknown versions are rejected.
+        assert!(FileMeta::decode(&buf[..buf.len() - 1]).is_err());
+        let mut longer = buf.clone();
+        longer.push(0);
+        assert!(FileMeta::decode(&longer).is_err());
+        let mut version = buf.clone();
+        version[0] = FileMeta::VERSION + 1;
+        assert!(FileMeta::decode(&version).is_err());
+        // Unsupported checksum types are rejected.
+        let mut checksum_type = buf;
+        checksum_type[21] = ChecksumType::XXHASH.bits();
+        assert!(FileMeta::decode(&checksum_type).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Entry points for the fuzz targets in `fuzz/`.
+//!
+//! Each function feeds arbitrary bytes to a decoder and must never panic or
+//! read out of bounds, whatever the input is. Inputs that crashed a decoder
+//! are kept in `fuzz/regressions/<target>/` and replayed by the tests below.
+
+use prost::Message;
+
+use crate::file::checksum::ChecksumType;
+use crate::file::types::{decode_block, FileMeta, Footer, IndexBlock, MetaBlock, FOOTER_LEN};
+use crate::page::base::PageRef;
+use crate::page::data::{Index, Key, Value};
+use crate::page::sort::{SortedPageIter, SortedPageKey, SortedPageRef, SortedPageValue};
+use crate::store::meta::VersionEdit;
+
+/// Decodes `data` as a sorted page of each kind and visits all its items.
+pub fn sorted_page(data: &[u8]) {
+    // Page content must be aligned, like the buffers pages are read into.
+    let mut buf = vec![0u64; data.len().div_ceil(8)];
+    let aligned = unsafe {
+        let ptr = buf.as_mut_ptr() as *mut u8;
+        ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+        std::slice::from_raw_parts(ptr, data.len())
+    };
+    let page = PageRef::new(aligned);
+    visit_sorted_page::<Key<'_>, Value<'_>>(page);
+    visit_sorted_page::<&[u8], &[u8]>(page);
+    visit_sorted_page::<&[u8], Index>(page);
+}
+
+fn visit_sorted_page<K, V>(page: PageRef<'_>)
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+{
+    if let Ok(page) = SortedPageRef::<K, V>::try_new(page) {
+        let mut count = 0;
+        for (k, _) in SortedPageIter::new(page.clone()) {
+            let _ = page.rank(&k);
+            count += 1;
+        }
+        assert_eq!(count, page.len());
+    }
+}
+
+/// Decodes `data` as a manifest record.
+pub fn version_edit(data: &[u8]) {
+    if let Ok(edit) = VersionEdit::decode(data) {
+        let buf = edit.encode_to_vec();
+        assert_eq!(VersionEdit::decode(buf.as_slice()).unwrap(), edit);
+    }
+}
+
+/// Decodes `data` as each kind of block of a page file, and as a meta block
+/// followed by a footer, checked by the checksum type read from the footer
+/// like a real file.
+pub fn file_blocks(data: &[u8]) {
+    let _ = Footer::decode(data);
+    let _ = MetaBlock::decode(data);
+    let _ = IndexBlock::decode(data);
+    let _ = FileMeta::decode(data);
+    for checksum_type in [ChecksumType::NONE, ChecksumType::CRC32] {
+        let _ = decode_block(checksum_type, data);
+    }
+    if let Some(footer_offset) = data.len().checked_sub(FOOTER_LEN) {
+        let (block, footer) = data.split_at(footer_offset);
+        if let Ok(footer) = Footer::decode(footer) {
+            if let Ok((_, payload)) = decode_block(footer.checksum_type, block) {
+                let _ = MetaBlock::decode(payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fuzz_dir() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fuzz")
+    }
+
+    fn replay(dir: &str, target: fn(&[u8])) {
+        for kind in ["corpus", "regressions"] {
+            let Ok(entries) = std::fs::read_dir(fuzz_dir().join(kind).join(dir)) else {
+                continue;
+            };
+            for entry in entries {
+                let data = std::fs::read(entry.unwrap().path()).unwrap();
+                target(&data);
+            }
+        }
+    }
+
+    #[test]
+    fn replay_sorted_page() {
+        replay("sorted_page", sorted_page);
+    }
+
+    #[test]
+    fn replay_version_edit() {
+        replay("version_edit", version_edit);
+    }
+
+    #[test]
+    fn replay_file_blocks() {
+        replay("file_blocks", file_blocks);
+    }
+
+    /// Writes the seed corpora from real encoded outputs, run it with
+    /// `cargo test -p db write_seed_corpus -- --ignored`.
+    #[test]
+    #[ignore]
+    fn write_seed_corpus() {
+        use crate::file::compression::Compression;
+        use crate::file::file_reader::BlockHandle;
+        use crate::file::types::{block_trailer, LsnRange};
+        use crate::page::base::tests::alloc_page;
+        use crate::page::base::{PageKind, PageMut, PageTier};
+        use crate::page::sort::SortedPageBuilder;
+        use crate::store::meta::{NewFile, StreamEdit};
+
+        let write = |target: &str, name: &str, data: &[u8]| {
+            let dir = fuzz_dir().join("corpus").join(target);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join(name), data).unwrap();
+        };
+
+        let leaf = [
+            (Key::new(b"a", 2), Value::Put(b"x".as_slice())),
+            (Key::new(b"a", 1), Value::Delete),
+            (Key::new(b"bc", 3), Value::Put(b"yz".as_slice())),
+        ];
+        let builder = SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&leaf);
+        let mut buf = alloc_page(builder.size());
+        builder.build(&mut PageMut::new(&mut buf));
+        write("sorted_page", "leaf", &buf);
+        let inner = [(b"a".as_slice(), Index::new(1, 2)), (b"b".as_slice(), Index::new(3, 4))];
+        let builder = SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&inner);
+        let mut buf = alloc_page(builder.size());
+        builder.build(&mut PageMut::new(&mut buf));
+        write("sorted_page", "inner", &buf);
+
+        let mut new_file = NewFile::from(4);
+        new_file.min_lsn = Some(1);
+        new_file.referenced_files = vec![1, 2];
+        let edit = VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: vec![new_file, NewFile::from(5)],
+                deleted_files: vec![1],
+            }),
+        };
+        write("version_edit", "edit", &edit.encode_to_vec());
+
+        let footer = Footer {
+            meta_handle: BlockHandle { offset: 100, length: 40 },
+            checksum_type: ChecksumType::CRC32,
+            compression: Compression::SNAPPY,
+        };
+        write("file_blocks", "footer", &footer.encode());
+        let file_meta = FileMeta {
+            file_id: 4,
+            file_size: 4096,
+            block_size: 4096,
+            referenced_groups: [1, 2].into_iter().collect(),
+            lsn_range: LsnRange::new(1, 9),
+            checksum_type: ChecksumType::CRC32,
+            compression: Compression::NONE,
+            page_groups: Default::default(),
+        };
+        write("file_blocks", "file_meta", &file_meta.encode());
+        let mut meta = MetaBlock {
+            lsn_range: LsnRange::new(1, 9),
+            ..Default::default()
+        };
+        meta.page_groups.insert(1, BlockHandle { offset: 0, length: 64 });
+        let mut block = meta.encode();
+        let trailer = block_trailer(ChecksumType::CRC32, Compression::NONE, &block);
+        write("file_blocks", "meta", &block);
+        block.extend_from_slice(&trailer);
+        write("file_blocks", "meta_block", &block);
+        let footer = Footer {
+            meta_handle: BlockHandle { offset: 0, length: block.len() as u64 },
+            checksum_type: ChecksumType::CRC32,
+            compression: Compression::NONE,
+        };
+        block.extend_from_slice(&footer.encode());
+        write("file_blocks", "meta_block_footer", &block);
+    }
+}
@@ -5,4 +5,7 @@ pub mod page;
 mod tree;
 mod store;
 mod utils;
-mod file;
\ No newline at end of file
+mod file;
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub mod fuzz;
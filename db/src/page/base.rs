@@ -6,13 +6,13 @@ use anyhow::Result;
 
 /// Page format {
 ///     epoch      : 6 bytes 世代用来追踪事务
-///     flags      : 1 bytes  用来标明是是否是叶子节点 和 数据节点
+///     flags      : 1 bytes  低 4 位标明是否是叶子节点 和 数据节点, 高 4 位是内容格式的版本
 ///     chain_len  : 1 bytes
 ///     chain_next : 8 bytes
 ///     content    : multiple bytes 内容具体存储
 /// }
 const PAGE_HEADER_LEN: usize = 6;
-const PAGE_CONTENT_LEN: usize = 16;
+pub(crate) const PAGE_CONTENT_LEN: usize = 16;
 const PAGE_EPOCH_MAX: u64 = (1 << 48) - 1;
 
 /// 针对 page的数据指针
@@ -36,6 +36,15 @@ impl PagePtr {
 
     pub fn set_flags(&mut self, flags: PageFlag) { unsafe { self.flags_ptr().write(flags.0) } }
     pub fn flags(&self) -> PageFlag { unsafe { PageFlag(self.flags_ptr().read()) } }
+
+    /// Returns the format version of the page content.
+    pub fn version(&self) -> u8 { self.flags().version() }
+    /// Sets the format version of the page content, it must be less than 16.
+    pub fn set_version(&mut self, version: u8) {
+        assert!(version <= PAGE_VERSION_MASK >> PAGE_VERSION_SHIFT);
+        let flags = self.flags().0 & !PAGE_VERSION_MASK;
+        self.set_flags(PageFlag(flags | (version << PAGE_VERSION_SHIFT)));
+    }
     pub fn set_epoch(&mut self, epoch: u64) {
         let data = epoch.to_le();
         let ptr = &data as *const u64 as *const u8;
@@ -214,6 +223,7 @@ impl PageBuild {
 }
 
 /// page的内容对象
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct PageInfo {
     meta: u64,
     next: u64,
@@ -279,8 +289,12 @@ impl PageFlag {
     pub fn new(kind: PageKind, tier: PageTier) -> Self { Self(kind as u8 | tier as u8) }
     pub fn kind(&self) -> PageKind { self.0.into() }
     pub fn tier(&self) -> PageTier { self.0.into() }
+    pub fn version(&self) -> u8 { (self.0 & PAGE_VERSION_MASK) >> PAGE_VERSION_SHIFT }
 }
 
+const PAGE_VERSION_MASK: u8 = 0b1111_0000;
+const PAGE_VERSION_SHIFT: u8 = 4;
+
 /// page 的 种类
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[repr(u8)]
@@ -346,7 +360,7 @@ impl From<u8> for PageKind {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::alloc::{alloc, Layout};
     use super::*;
 
@@ -377,6 +391,12 @@ mod tests {
             assert!(page.kind().is_split());
         }
 
+        assert_eq!(page.version(), 0);
+        page.set_version(15);
+        assert_eq!(page.version(), 15);
+        assert!(page.tier().is_inner());
+        assert!(page.kind().is_split());
+
         assert_eq!(page.epoch(), 0);
         page.set_epoch(1);
         assert_eq!(page.epoch(), 1);
@@ -1,5 +1,7 @@
 use std::{mem, slice};
 
+use crate::error::{Error, Result};
+
 /// Allows an object to be encoded and decoded.
 pub(crate) trait Codec {
     /// Returns the exact size to encode the object.
@@ -18,6 +20,12 @@ pub(crate) trait Codec {
     ///
     /// The decoder must have enough data to decode the object.
     unsafe fn decode_from(decoder: &mut Decoder) -> Self;
+
+    /// Decodes an object from untrusted data, returns [`Error::Corrupted`]
+    /// instead of reading out of bounds or panicking on invalid data.
+    fn decode_checked(decoder: &mut Decoder) -> Result<Self>
+        where
+            Self: Sized;
 }
 
 // An unsafe, little-endian encoder.
@@ -74,6 +82,19 @@ impl Encoder {
         let cursor = self.take(v.len());
         cursor.copy_from_nonoverlapping(v.as_ptr(), v.len());
     }
+
+    /// Finishes the encoder, asserts in debug builds that exactly the reserved
+    /// bytes are written.
+    ///
+    /// A mismatch means some [`Codec::encode_size`] disagrees with its
+    /// [`Codec::encode_to`].
+    pub(super) fn finish(self) {
+        debug_assert_eq!(
+            unsafe { self.offset() },
+            self.len,
+            "encoded size doesn't match the reserved size"
+        );
+    }
 }
 
 // An unsafe, little-endian decoder.
@@ -95,6 +116,17 @@ macro_rules! get_int {
     };
 }
 
+macro_rules! get_int_checked {
+    ($name:ident, $get:ident, $t:ty) => {
+        pub(super) fn $name(&mut self) -> Result<$t> {
+            if mem::size_of::<$t>() > self.remaining_checked() {
+                return Err(Error::Corrupted);
+            }
+            Ok(unsafe { self.$get() })
+        }
+    };
+}
+
 impl Decoder {
     pub(super) fn new(buf: &[u8]) -> Self {
         Self {
@@ -131,4 +163,123 @@ impl Decoder {
         let cursor = self.take(len);
         slice::from_raw_parts(cursor, len)
     }
+
+    get_int_checked!(get_u8_checked, get_u8, u8);
+    get_int_checked!(get_u32_checked, get_u32, u32);
+    get_int_checked!(get_u64_checked, get_u64, u64);
+
+    /// Like [`Self::get_slice`], but returns [`Error::Corrupted`] if there
+    /// is not enough data.
+    pub(super) fn get_slice_checked<'a>(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.remaining_checked() {
+            return Err(Error::Corrupted);
+        }
+        Ok(unsafe { self.get_slice(len) })
+    }
+
+    pub(super) fn remaining_checked(&self) -> usize {
+        unsafe { self.remaining() }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::fmt::Debug;
+    use super::*;
+
+    /// Asserts that `value` encodes to exactly `encode_size()` bytes, and that
+    /// both decoders consume all of them and return the same value.
+    pub(crate) fn assert_codec_roundtrip<T>(value: &T)
+        where
+            T: Codec + Debug + PartialEq,
+    {
+        let size = value.encode_size();
+        // One more byte to catch writes past the reserved size.
+        let mut buf = vec![0u8; size + 1];
+        let mut enc = Encoder::new(&mut buf);
+        unsafe {
+            value.encode_to(&mut enc);
+            assert_eq!(enc.offset(), size, "encode_size != bytes written");
+        }
+        assert_eq!(buf[size], 0, "write past encode_size");
+
+        let mut dec = Decoder::new(&buf[..size]);
+        let decoded = unsafe { T::decode_from(&mut dec) };
+        assert_eq!(&decoded, value);
+        assert_eq!(dec.remaining_checked(), 0, "encode_size != bytes consumed");
+
+        let mut dec = Decoder::new(&buf[..size]);
+        assert_eq!(&T::decode_checked(&mut dec).unwrap(), value);
+        assert_eq!(dec.remaining_checked(), 0, "encode_size != bytes consumed");
+        for len in 0..size {
+            // Truncated data is rejected instead of read out of bounds, except
+            // for trailing bytes that the codec doesn't length-prefix.
+            let mut dec = Decoder::new(&buf[..len]);
+            if let Ok(decoded) = T::decode_checked(&mut dec) {
+                assert_ne!(&decoded, value);
+            }
+        }
+    }
+
+    /// A codec that writes one more byte than it reserves.
+    #[derive(Debug, PartialEq)]
+    struct MisSized(u32);
+
+    impl Codec for MisSized {
+        fn encode_size(&self) -> usize {
+            mem::size_of::<u32>()
+        }
+
+        unsafe fn encode_to(&self, enc: &mut Encoder) {
+            enc.put_u32(self.0);
+            enc.put_u8(0);
+        }
+
+        unsafe fn decode_from(dec: &mut Decoder) -> Self {
+            Self(dec.get_u32())
+        }
+
+        fn decode_checked(dec: &mut Decoder) -> Result<Self> {
+            Ok(Self(dec.get_u32_checked()?))
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "encode_size != bytes written")]
+    fn codec_roundtrip_mis_sized() {
+        assert_codec_roundtrip(&MisSized(1));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn encoder_overflow() {
+        let value = MisSized(1);
+        let mut buf = vec![0u8; value.encode_size()];
+        let mut enc = Encoder::new(&mut buf);
+        unsafe { value.encode_to(&mut enc) };
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "encoded size doesn't match the reserved size")]
+    fn encoder_finish_short() {
+        let mut buf = [0u8; 8];
+        let mut enc = Encoder::new(&mut buf);
+        unsafe { enc.put_u32(1) };
+        enc.finish();
+    }
+
+    #[test]
+    fn decoder_checked() {
+        let buf = [1u8, 2, 0, 0, 0, 3];
+        let mut dec = Decoder::new(&buf);
+        assert_eq!(dec.get_u8_checked().unwrap(), 1);
+        assert_eq!(dec.get_u32_checked().unwrap(), 2);
+        assert!(dec.get_u64_checked().is_err());
+        assert!(dec.get_slice_checked(2).is_err());
+        assert_eq!(dec.get_slice_checked(1).unwrap(), &[3]);
+        assert!(dec.get_u8_checked().is_err());
+        assert_eq!(dec.remaining_checked(), 0);
+    }
 }
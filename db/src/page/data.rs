@@ -1,5 +1,9 @@
 use std::cmp::Ordering;
 
+/// A versioned key.
+///
+/// Keys are ordered by the raw key ascendingly and the LSN descendingly, so
+/// the newest version of a raw key comes first.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Key<'a> {
     pub(crate) raw: &'a [u8],
@@ -11,6 +15,16 @@ impl<'a> Key<'a> {
         Self { raw, lsn }
     }
 
+    /// Returns the smallest key of `raw`, which sorts before all its versions.
+    pub(crate) const fn min_for(raw: &'a [u8]) -> Self {
+        Self::new(raw, u64::MAX)
+    }
+
+    /// Returns the largest key of `raw`, which sorts after all its versions.
+    pub(crate) const fn max_for(raw: &'a [u8]) -> Self {
+        Self::new(raw, 0)
+    }
+
     #[inline]
     pub(crate) fn len(&self) -> usize {
         self.raw.len() + core::mem::size_of::<u64>()
@@ -53,6 +67,9 @@ impl<'a> Range<'a> {
 pub(crate) enum Value<'a> {
     Put(&'a [u8]),
     Delete,
+    /// A put whose value is stored out of line, in the blob region of the
+    /// file that holds the page.
+    Blob(BlobRef),
 }
 
 impl<'a> Value<'a> {
@@ -61,10 +78,22 @@ impl<'a> Value<'a> {
         match self {
             Value::Put(v) => v.len(),
             Value::Delete => 0,
+            Value::Blob(blob) => blob.size as usize,
         }
     }
 }
 
+/// Locates a value in the blob region of a file.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct BlobRef {
+    /// The offset of the blob block from the start of the blob region.
+    pub(crate) offset: u64,
+    /// The length of the blob block, including its trailer.
+    pub(crate) length: u64,
+    /// The size of the value.
+    pub(crate) size: u64,
+}
+
 /// An index to a child page.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Index {
@@ -78,4 +107,22 @@ impl Index {
     pub(crate) const fn new(id: u64, epoch: u64) -> Self {
         Self { id, epoch }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_min_max_for() {
+        let raw = b"b".as_slice();
+        let min = Key::min_for(raw);
+        let max = Key::max_for(raw);
+        for lsn in [0, 1, 42, u64::MAX - 1, u64::MAX] {
+            let key = Key::new(raw, lsn);
+            assert!(min <= key && key <= max);
+            assert!(Key::max_for(b"a") < key);
+            assert!(key < Key::min_for(b"ba"));
+        }
+        assert!(Key::new(raw, 2) < Key::new(raw, 1));
+    }
+}
@@ -105,7 +105,65 @@ impl<'a, T: Clone + Ord> SeekableIterator<T> for SliceIter<'a, T> {
     }
 }
 
+/// An iterator that caches the items read from an iterator that can't rewind
+/// cheaply (e.g. one reading pages from a file), so that rewinding replays the
+/// cached items instead of reading them again.
+pub(crate) struct MaterializedIter<I>
+    where
+        I: Iterator,
+{
+    iter: I,
+    items: Vec<I::Item>,
+    next: usize,
+}
+
+impl<I> MaterializedIter<I>
+    where
+        I: Iterator,
+{
+    pub(crate) fn new(iter: I) -> Self {
+        Self {
+            iter,
+            items: Vec::new(),
+            next: 0,
+        }
+    }
+}
+
+impl<I> Iterator for MaterializedIter<I>
+    where
+        I: Iterator,
+        I::Item: Clone,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.items.get(self.next) {
+            self.next += 1;
+            return Some(item.clone());
+        }
+        let item = self.iter.next()?;
+        self.items.push(item.clone());
+        self.next += 1;
+        Some(item)
+    }
+}
+
+impl<I> RewindableIterator for MaterializedIter<I>
+    where
+        I: Iterator,
+        I::Item: Clone,
+{
+    fn rewind(&mut self) {
+        self.next = 0;
+    }
+}
+
 /// A wrapper to order an [`Iterator`] by its next item and rank.
+///
+/// Iterators are ordered by the key of their next items, and by their rank
+/// when the keys are equal, so the order is total and doesn't depend on the
+/// heap layout.
 #[derive(Clone, Debug)]
 pub(crate) struct OrderedIter<I>
     where
@@ -277,6 +335,20 @@ impl<I> RewindableIterator for MergingIter<I>
     }
 }
 
+impl<I> MergingIter<I>
+    where
+        I: Iterator,
+        OrderedIter<I>: RewindableIterator<Item = I::Item> + Ord,
+{
+    /// Positions the iterator at the first item.
+    ///
+    /// There is no `seek_to_last`, since the merging heap only orders the
+    /// children by their next item in the forward direction.
+    pub(crate) fn seek_to_first(&mut self) {
+        self.rewind();
+    }
+}
+
 impl<I, T> SeekableIterator<T> for MergingIter<I>
     where
         T: ?Sized,
@@ -327,6 +399,13 @@ impl<I, K, V> MergingIterBuilder<I>
     }
 
     /// Adds an iterator to the builder.
+    ///
+    /// Iterators must be added from the newest source to the oldest, i.e. the
+    /// write buffers first, then the files from the newest to the oldest. When
+    /// the same key (the raw key and the LSN) exists in several iterators,
+    /// e.g. while a write buffer is being flushed, the items are yielded in
+    /// the order their iterators were added, so readers that take the first
+    /// item always see the newest source.
     pub(crate) fn add(&mut self, iter: I) {
         let rank = self.iters.len();
         self.iters.push(Reverse(OrderedIter::new(iter, rank)));
@@ -371,6 +450,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn materialized_iter() {
+        let mut reads = 0;
+        let mut iter = MaterializedIter::new([1, 2, 3].into_iter().inspect(|_| reads += 1));
+        assert_eq!(iter.next(), Some(1));
+        iter.rewind();
+        for _ in 0..2 {
+            assert_eq!(iter.by_ref().collect::<Vec<_>>(), vec![1, 2, 3]);
+            iter.rewind();
+        }
+        drop(iter);
+        assert_eq!(reads, 3);
+    }
+
     #[test]
     fn merging_iter() {
         let input = [
@@ -406,6 +499,10 @@ mod tests {
             iter.rewind();
         }
 
+        assert_eq!(iter.next(), Some((1, "a")));
+        iter.seek_to_first();
+        assert_eq!(iter.next(), Some((1, "a")));
+
         assert!(!iter.seek(&(0, "")));
         assert_eq!(iter.next(), Some((1, "a")));
         assert_eq!(iter.next(), Some((1, "c")));
@@ -421,4 +518,51 @@ mod tests {
         assert_eq!(iter.next(), Some((7, "d")));
         assert_eq!(iter.next(), Some((8, "c")));
     }
+
+    #[test]
+    fn merging_iter_equal_keys() {
+        use crate::page::data::{Key, Value};
+
+        // The same version of "b" is in the write buffer and the file it is
+        // being flushed to.
+        let buffer = [
+            (Key::new(b"b", 5), Value::Put(b"buffer".as_slice())),
+            (Key::new(b"c", 6), Value::Delete),
+        ];
+        let new_file = [
+            (Key::new(b"a", 4), Value::Put(b"new".as_slice())),
+            (Key::new(b"b", 5), Value::Put(b"new".as_slice())),
+        ];
+        let old_file = [
+            (Key::new(b"b", 5), Value::Put(b"old".as_slice())),
+            (Key::new(b"b", 2), Value::Put(b"old".as_slice())),
+        ];
+        let expect = vec![
+            (Key::new(b"a", 4), Value::Put(b"new".as_slice())),
+            (Key::new(b"b", 5), Value::Put(b"buffer".as_slice())),
+            (Key::new(b"b", 5), Value::Put(b"new".as_slice())),
+            (Key::new(b"b", 5), Value::Put(b"old".as_slice())),
+            (Key::new(b"b", 2), Value::Put(b"old".as_slice())),
+            (Key::new(b"c", 6), Value::Delete),
+        ];
+        for _ in 0..10 {
+            let mut builder = MergingIterBuilder::new();
+            for slice in [&buffer[..], &new_file[..], &old_file[..]] {
+                builder.add(SliceIter::new(slice));
+            }
+            let mut iter = builder.build();
+            for _ in 0..2 {
+                assert_eq!(iter.by_ref().collect::<Vec<_>>(), expect);
+                iter.rewind();
+            }
+        }
+
+        // The rank, not the content, decides the order of equal keys.
+        let mut builder = MergingIterBuilder::new();
+        builder.add(SliceIter::new(&old_file));
+        builder.add(SliceIter::new(&buffer));
+        let mut iter = builder.build();
+        assert_eq!(iter.next(), Some(old_file[0]));
+        assert_eq!(iter.next(), Some(buffer[0]));
+    }
 }
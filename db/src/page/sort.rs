@@ -3,11 +3,18 @@ use std::{mem, slice};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::ops::{Deref, Range};
-use crate::page::base::{PageBuild, PageKind, PageMut, PageRef, PageTier};
+use crate::error::{Error, Result as CrateResult};
+use crate::page::base::{PageBuild, PageKind, PageMut, PageRef, PageTier, PAGE_CONTENT_LEN};
 use crate::page::codec::{Codec, Decoder, Encoder};
-use crate::page::data::{Index, Key, Value};
+use crate::page::data::{BlobRef, Index, Key, Value};
 use crate::page::iter::{ItemIter, RewindableIterator, SeekableIterator, SliceIter};
 
+/// The format version of the content of sorted pages, stored in the page
+/// flags.
+///
+/// Version 2 adds the out-of-line values of [`Value::Blob`].
+pub(crate) const SORTED_PAGE_VERSION: u8 = 2;
+
 pub(crate) struct SortedPageBuilder<I> {
     base: PageBuild,
     iter: Option<I>,
@@ -49,6 +56,21 @@ impl<I, K, V> SortedPageBuilder<I>
         self
     }
 
+    /// Creates a [`SortedPageBuilder`] that will build a page from the given
+    /// iterator, whose number of items and encoded content size (excluding
+    /// the item offsets) are already known by the caller.
+    ///
+    /// This skips the sizing pass of [`Self::with_iter`], so the iterator is
+    /// only iterated once when building the page. The declared size must
+    /// match the items exactly, building panics otherwise.
+    pub(crate) fn with_sized_iter(mut self, iter: I, num_items: usize, content_size: usize) -> Self {
+        self.num_items = num_items;
+        self.content_size = content_size + num_items * mem::size_of::<u32>();
+        assert!(self.content_size <= u32::MAX as usize);
+        self.iter = Some(iter);
+        self
+    }
+
     /// Returns the size of the page that will be built.
     pub(crate) fn size(&self) -> usize {
         self.base.size(self.content_size)
@@ -57,13 +79,16 @@ impl<I, K, V> SortedPageBuilder<I>
     pub(crate) fn build(mut self, page: &mut PageMut<'_>) {
         assert!(page.size() >= self.size());
         self.base.build(page);
+        page.set_version(SORTED_PAGE_VERSION);
         if let Some(iter) = self.iter.as_mut() {
             unsafe {
-                let mut buf = SortedPageBuf::new(page.content_mut(), self.num_items);
+                let content = &mut page.content_mut()[..self.content_size];
+                let mut buf = SortedPageBuf::new(content, self.num_items);
                 iter.rewind();
                 for (k, v) in iter {
                     buf.add(k, v);
                 }
+                buf.finish();
             }
         }
     }
@@ -114,11 +139,32 @@ impl<K, V> SortedPageBuf<K, V>
     }
 
     unsafe fn add(&mut self, key: K, value: V) {
+        // 大小可能由调用方声明, 写入之前检查, 避免越界写入
+        assert!(
+            self.offsets.remaining() >= mem::size_of::<u32>()
+                && self.payload.remaining() >= key.encode_size() + value.encode_size(),
+            "items exceed the declared size"
+        );
         // 把整块内存 分为两块 [存储偏移量, 存储key + value]
         let offset = self.offsets.len() + self.payload.offset(); // 游标和buf头的偏移
         self.offsets.put_u32(offset as u32); // 将写入位置 放置在 索引区
         key.encode_to(&mut self.payload);
         value.encode_to(&mut self.payload);
+        debug_assert_eq!(
+            self.offsets.len() + self.payload.offset() - offset,
+            key.encode_size() + value.encode_size(),
+            "encoded size doesn't match encode_size"
+        );
+    }
+
+    /// 检查 offsets 和 payload 都恰好写满
+    fn finish(self) {
+        assert!(
+            unsafe { self.offsets.remaining() == 0 && self.payload.remaining() == 0 },
+            "items don't fill the declared size"
+        );
+        self.offsets.finish();
+        self.payload.finish();
     }
 }
 
@@ -136,7 +182,9 @@ impl<'a, K, V> SortedPageRef<'a, K, V>
         K: SortedPageKey,
         V: SortedPageValue,
 {
+    /// Creates a [`SortedPageRef`] from a page built by [`SortedPageBuilder`].
     pub(crate) fn new(page: PageRef<'a>) -> Self {
+        debug_assert_eq!(page.version(), SORTED_PAGE_VERSION);
         let content = page.content();
         let offsets = unsafe { // 索引位置
             let ptr = content.as_ptr() as *const u32;
@@ -156,6 +204,46 @@ impl<'a, K, V> SortedPageRef<'a, K, V>
         }
     }
 
+    /// Creates a [`SortedPageRef`] from a page read from outside.
+    ///
+    /// Returns [`Error::Corrupted`] if the page has an unknown format version,
+    /// or if the offsets or any item are invalid, so that the returned page
+    /// can be decoded without checks. The page content must be aligned to 4
+    /// bytes.
+    pub(crate) fn try_new(page: PageRef<'a>) -> CrateResult<Self> {
+        if page.size() < PAGE_CONTENT_LEN || page.version() != SORTED_PAGE_VERSION {
+            return Err(Error::Corrupted);
+        }
+        let content = page.content();
+        if !content.is_empty() {
+            if content.len() < mem::size_of::<u32>()
+                || content.as_ptr().align_offset(mem::align_of::<u32>()) != 0
+            {
+                return Err(Error::Corrupted);
+            }
+            let mut dec = Decoder::new(content);
+            let offsets_size = dec.get_u32_checked()? as usize;
+            if !offsets_size.is_multiple_of(mem::size_of::<u32>()) || offsets_size > content.len() {
+                return Err(Error::Corrupted);
+            }
+            let mut offsets = vec![offsets_size];
+            for _ in 1..offsets_size / mem::size_of::<u32>() {
+                offsets.push(dec.get_u32_checked()? as usize);
+            }
+            offsets.push(content.len());
+            for item in offsets.windows(2) {
+                let (start, end) = (item[0], item[1]);
+                if start > end || end > content.len() {
+                    return Err(Error::Corrupted);
+                }
+                let mut dec = Decoder::new(&content[start..end]);
+                K::decode_checked(&mut dec)?;
+                V::decode_checked(&mut dec)?;
+            }
+        }
+        Ok(Self::new(page))
+    }
+
     /// Returns the number of items in the page.
     pub(crate) fn len(&self) -> usize {
         self.offsets.len()
@@ -186,7 +274,7 @@ impl<'a, K, V> SortedPageRef<'a, K, V>
         let mut left = 0;
         let mut right = self.len();
         while left < right {
-            let mid = (left + right) / 2;
+            let mid = left + (right - left) / 2;
             let key = unsafe {
                 let item = self.item(mid).unwrap();
                 let mut dec = Decoder::new(item);
@@ -230,6 +318,28 @@ impl<'a, K, V> SortedPageRef<'a, K, V>
         None
     }
 
+    /// Renders the page layout: the header, the offsets array and the bytes of
+    /// each item. Used by golden tests to catch on-disk format changes.
+    #[cfg(test)]
+    pub(crate) fn hex_dump(&self) -> String {
+        use std::fmt::Write;
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")
+        }
+
+        let data = self.page.data();
+        let header = &data[..data.len() - self.content.len()];
+        let mut out = String::new();
+        writeln!(out, "header: {}", hex(header)).unwrap();
+        let offsets: Vec<_> = (0..self.len()).map(|i| self.item_offset(i).unwrap()).collect();
+        writeln!(out, "offsets: {offsets:?}").unwrap();
+        for i in 0..self.len() {
+            writeln!(out, "item {i}: {}", hex(self.item(i).unwrap())).unwrap();
+        }
+        out
+    }
+
     fn item(&self, index: usize) -> Option<&[u8]> {
         if let Some(offset) = self.item_offset(index) {
             let next_offset = self.item_offset(index + 1).unwrap_or(self.content.len());
@@ -267,12 +377,46 @@ impl<'a, K, V, T> From<T> for SortedPageRef<'a, K, V>
 pub(crate) struct SortedPageIter<'a, K, V> {
     page: SortedPageRef<'a, K, V>,
     next: usize,
+    // 反向迭代的位置, 指向最后一个未返回元素的下一个位置
+    back: usize,
 }
 
-impl<'a, K, V> SortedPageIter<'a, K, V> {
+impl<'a, K, V> SortedPageIter<'a, K, V>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+{
     /// Creates a [`SortedPageIter`] over items in the given page.
     pub(crate) fn new(page: SortedPageRef<'a, K, V>) -> Self {
-        Self { page, next: 0 }
+        let back = page.len();
+        Self { page, next: 0, back }
+    }
+
+    /// Positions the iterator at the first item.
+    pub(crate) fn seek_to_first(&mut self) {
+        self.next = 0;
+        self.back = self.page.len();
+    }
+
+    /// Positions the iterator at the last item, so that both `next` and
+    /// `next_back` return the last item.
+    pub(crate) fn seek_to_last(&mut self) {
+        self.back = self.page.len();
+        self.next = self.back.saturating_sub(1);
+    }
+
+    fn seek_to(&mut self, rank: Result<usize, usize>) -> bool {
+        self.back = self.page.len();
+        match rank {
+            Ok(i) => {
+                self.next = i;
+                true
+            }
+            Err(i) => {
+                self.next = i;
+                false
+            }
+        }
     }
 }
 
@@ -295,9 +439,25 @@ impl<'a, K, V> Iterator for SortedPageIter<'a, K, V>
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(item) = self.page.get(self.next) {
-            self.next += 1;
-            Some(item)
+        if self.next < self.back {
+            if let Some(item) = self.page.get(self.next) {
+                self.next += 1;
+                return Some(item);
+            }
+        }
+        None
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for SortedPageIter<'a, K, V>
+    where
+        K: SortedPageKey,
+        V: SortedPageValue,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.next < self.back {
+            self.back -= 1;
+            self.page.get(self.back)
         } else {
             None
         }
@@ -310,7 +470,7 @@ impl<'a, K, V> RewindableIterator for SortedPageIter<'a, K, V>
         V: SortedPageValue,
 {
     fn rewind(&mut self) {
-        self.next = 0;
+        self.seek_to_first();
     }
 }
 
@@ -319,16 +479,8 @@ impl<'a, V> SeekableIterator<Key<'_>> for SortedPageIter<'a, Key<'_>, V>
         V: SortedPageValue,
 {
     fn seek(&mut self, target: &Key<'_>) -> bool {
-        match self.page.rank(target) {
-            Ok(i) => {
-                self.next = i;
-                true
-            }
-            Err(i) => {
-                self.next = i;
-                false
-            }
-        }
+        let rank = self.page.rank(target);
+        self.seek_to(rank)
     }
 }
 
@@ -337,16 +489,8 @@ impl<'a, V> SeekableIterator<[u8]> for SortedPageIter<'a, &'a [u8], V>
         V: SortedPageValue,
 {
     fn seek(&mut self, target: &[u8]) -> bool {
-        match self.page.rank(target) {
-            Ok(i) => {
-                self.next = i;
-                true
-            }
-            Err(i) => {
-                self.next = i;
-                false
-            }
-        }
+        let rank = self.page.rank(target);
+        self.seek_to(rank)
     }
 }
 
@@ -422,6 +566,11 @@ impl Codec for &[u8] {
         let len = dec.get_u32() as usize;
         dec.get_slice(len)
     }
+
+    fn decode_checked(dec: &mut Decoder) -> CrateResult<Self> {
+        let len = dec.get_u32_checked()? as usize;
+        dec.get_slice_checked(len)
+    }
 }
 
 impl SortedPageKey for &[u8] {
@@ -449,6 +598,12 @@ impl Codec for Key<'_> {
         let lsn = dec.get_u64();
         Self::new(raw, lsn)
     }
+
+    fn decode_checked(dec: &mut Decoder) -> CrateResult<Self> {
+        let raw = Codec::decode_checked(dec)?;
+        let lsn = dec.get_u64_checked()?;
+        Ok(Self::new(raw, lsn))
+    }
 }
 
 impl SortedPageKey for Key<'_> {
@@ -465,12 +620,14 @@ impl SortedPageKey for Key<'_> {
 /// These values are persisted to disk, don't change them.
 const VALUE_KIND_PUT: u8 = 0;
 const VALUE_KIND_DELETE: u8 = 1;
+const VALUE_KIND_BLOB: u8 = 2;
 
 impl Codec for Value<'_> {
     fn encode_size(&self) -> usize {
         1 + match self {
             Self::Put(v) => v.len(),
             Self::Delete => 0,
+            Self::Blob(_) => mem::size_of::<u64>() * 3,
         }
     }
 
@@ -481,6 +638,12 @@ impl Codec for Value<'_> {
                 enc.put_slice(v);
             }
             Value::Delete => enc.put_u8(VALUE_KIND_DELETE),
+            Value::Blob(blob) => {
+                enc.put_u8(VALUE_KIND_BLOB);
+                enc.put_u64(blob.offset);
+                enc.put_u64(blob.length);
+                enc.put_u64(blob.size);
+            }
         }
     }
 
@@ -489,9 +652,29 @@ impl Codec for Value<'_> {
         match kind {
             VALUE_KIND_PUT => Self::Put(dec.get_slice(dec.remaining())),
             VALUE_KIND_DELETE => Self::Delete,
+            VALUE_KIND_BLOB => Self::Blob(BlobRef {
+                offset: dec.get_u64(),
+                length: dec.get_u64(),
+                size: dec.get_u64(),
+            }),
+            // Pages are validated by `SortedPageRef::try_new` before they
+            // are decoded without checks.
             _ => unreachable!(),
         }
     }
+
+    fn decode_checked(dec: &mut Decoder) -> CrateResult<Self> {
+        match dec.get_u8_checked()? {
+            VALUE_KIND_PUT => Ok(Self::Put(dec.get_slice_checked(dec.remaining_checked())?)),
+            VALUE_KIND_DELETE => Ok(Self::Delete),
+            VALUE_KIND_BLOB => Ok(Self::Blob(BlobRef {
+                offset: dec.get_u64_checked()?,
+                length: dec.get_u64_checked()?,
+                size: dec.get_u64_checked()?,
+            })),
+            _ => Err(Error::Corrupted),
+        }
+    }
 }
 
 impl Codec for Index {
@@ -509,4 +692,318 @@ impl Codec for Index {
         let epoch = dec.get_u64();
         Self::new(id, epoch)
     }
-}
\ No newline at end of file
+
+    fn decode_checked(dec: &mut Decoder) -> CrateResult<Self> {
+        let id = dec.get_u64_checked()?;
+        let epoch = dec.get_u64_checked()?;
+        Ok(Self::new(id, epoch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+    use crate::page::base::tests::alloc_page;
+    use crate::page::iter::{MaterializedIter, MergingIterBuilder};
+
+    fn build_page<I, K, V>(builder: SortedPageBuilder<I>) -> Box<[u8]>
+        where
+            I: RewindableIterator<Item = (K, V)>,
+            K: SortedPageKey,
+            V: SortedPageValue,
+    {
+        let mut buf = alloc_page(builder.size());
+        let mut page = PageMut::new(&mut buf);
+        builder.build(&mut page);
+        buf
+    }
+
+    #[test]
+    fn sorted_page_golden() {
+        use crate::page::data::{Key, Value};
+
+        let items = [
+            (Key::new(b"a", 2), Value::Put(b"x".as_slice())),
+            (Key::new(b"a", 1), Value::Delete),
+            (Key::new(b"bc", 3), Value::Put(b"yz".as_slice())),
+        ];
+        let buf = build_page(SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items));
+        let page = SortedPageRef::<Key<'_>, Value<'_>>::new(PageRef::new(&buf));
+        let expect = "\
+header: 00 00 00 00 00 00 20 01 00 00 00 00 00 00 00 00
+offsets: [12, 27, 41]
+item 0: 01 00 00 00 61 02 00 00 00 00 00 00 00 00 78
+item 1: 01 00 00 00 61 01 00 00 00 00 00 00 00 01
+item 2: 02 00 00 00 62 63 03 00 00 00 00 00 00 00 00 79 7a
+";
+        assert_eq!(page.hex_dump(), expect);
+    }
+
+    #[test]
+    fn sorted_page_version() {
+        let items = [(b"a".as_slice(), b"1".as_slice())];
+        let mut buf = build_page(SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&items));
+        let page = SortedPageRef::<&[u8], &[u8]>::try_new(PageRef::new(&buf)).unwrap();
+        assert_eq!(page.version(), SORTED_PAGE_VERSION);
+        assert!(page.tier().is_inner());
+        assert_eq!(page.get(0), Some(items[0]));
+
+        PageMut::new(&mut buf).set_version(SORTED_PAGE_VERSION + 1);
+        assert!(SortedPageRef::<&[u8], &[u8]>::try_new(PageRef::new(&buf)).is_err());
+    }
+
+    #[test]
+    fn codec_roundtrip() {
+        use rand::Rng;
+        use crate::page::codec::tests::assert_codec_roundtrip;
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            let len = rng.gen_range(0..64);
+            let raw: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            assert_codec_roundtrip(&raw.as_slice());
+            assert_codec_roundtrip(&Key::new(&raw, rng.gen()));
+            assert_codec_roundtrip(&Value::Put(&raw));
+            assert_codec_roundtrip(&Index::new(rng.gen(), rng.gen()));
+        }
+        assert_codec_roundtrip(&Value::Delete);
+        assert_codec_roundtrip(&Value::Blob(BlobRef {
+            offset: 1 << 40,
+            length: 4101,
+            size: 4096,
+        }));
+    }
+
+    #[test]
+    fn sorted_page_empty_key_and_value() {
+        use crate::page::data::{Key, Value};
+
+        let items = [
+            (Key::new(b"", 3), Value::Put(b"".as_slice())),
+            (Key::new(b"", 2), Value::Delete),
+            (Key::new(b"", 1), Value::Put(b"x".as_slice())),
+            (Key::new(b"a", 1), Value::Put(b"".as_slice())),
+        ];
+        let buf = build_page(SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items));
+        let page = SortedPageRef::<Key<'_>, Value<'_>>::try_new(PageRef::new(&buf)).unwrap();
+        let decoded: Vec<_> = SortedPageIter::new(page.clone()).collect();
+        assert_eq!(decoded, items);
+        // An empty put is not a tombstone.
+        assert_ne!(decoded[0].1, Value::Delete);
+        assert_eq!(page.rank(&Key::new(b"", u64::MAX)), Err(0));
+        assert_eq!(page.rank(&Key::new(b"", 2)), Ok(1));
+
+        let items = [(b"".as_slice(), b"".as_slice()), (b"a".as_slice(), b"".as_slice())];
+        let buf = build_page(SortedPageBuilder::new(PageTier::Inner, PageKind::Data).with_slice(&items));
+        let page = SortedPageRef::<&[u8], &[u8]>::try_new(PageRef::new(&buf)).unwrap();
+        assert_eq!(page.rank(b"".as_slice()), Ok(0));
+        let mut iter = SortedPageIter::new(page);
+        assert!(iter.seek(b"".as_slice()));
+        assert_eq!(iter.next(), Some(items[0]));
+        assert_eq!(iter.collect::<Vec<_>>(), items[1..]);
+    }
+
+    #[test]
+    fn sorted_page_rank() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let mut keys: Vec<Vec<u8>> = (0..rng.gen_range(0..64))
+                .map(|_| (0..rng.gen_range(0..4)).map(|_| rng.gen_range(0..8)).collect())
+                .collect();
+            keys.sort();
+            keys.dedup();
+            let items: Vec<(&[u8], &[u8])> = keys.iter().map(|k| (k.as_slice(), [].as_slice())).collect();
+            let buf = build_page(SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items));
+            let page = SortedPageRef::<&[u8], &[u8]>::new(buf.as_ref().into());
+            assert_eq!(page.len(), keys.len());
+
+            for _ in 0..32 {
+                let target: Vec<u8> = (0..rng.gen_range(0..4)).map(|_| rng.gen_range(0..8)).collect();
+                let index = keys.iter().take_while(|k| **k < target).count();
+                let expect = if keys.get(index) == Some(&target) {
+                    Ok(index)
+                } else {
+                    Err(index)
+                };
+                assert_eq!(page.rank(target.as_slice()), expect);
+            }
+        }
+    }
+
+    #[test]
+    fn sorted_page_build_from_merging_iter() {
+        let sources: [Vec<(Key<'_>, Value<'_>)>; 3] = [
+            vec![
+                (Key::new(b"a", 3), Value::Put(b"a3")),
+                (Key::new(b"c", 1), Value::Put(b"c1")),
+            ],
+            vec![
+                (Key::new(b"a", 2), Value::Delete),
+                (Key::new(b"b", 2), Value::Put(b"b2")),
+                (Key::new(b"c", 2), Value::Put(b"c2")),
+            ],
+            vec![
+                (Key::new(b"b", 1), Value::Put(b"b1")),
+                (Key::new(b"d", 1), Value::Put(b"d1")),
+            ],
+        ];
+
+        // Collects the merged items into a vector first.
+        let mut builder = MergingIterBuilder::new();
+        for source in sources.iter() {
+            builder.add(SliceIter::new(source));
+        }
+        let items: Vec<_> = builder.build().collect();
+        let expect = build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&items),
+        );
+
+        // Builds the page from the merging iterator directly.
+        let mut builder = MergingIterBuilder::new();
+        for source in sources.iter() {
+            builder.add(SliceIter::new(source));
+        }
+        let page = build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(builder.build()),
+        );
+        assert_eq!(page, expect);
+
+        // Children that can't rewind cheaply are only read once.
+        let reads = Cell::new(0);
+        let mut builder = MergingIterBuilder::new();
+        for source in sources.iter() {
+            let iter = source.iter().cloned().inspect(|_| reads.set(reads.get() + 1));
+            builder.add(MaterializedIter::new(iter));
+        }
+        let page = build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(builder.build()),
+        );
+        assert_eq!(page, expect);
+        assert_eq!(reads.get(), items.len());
+
+        // Builds the page with the known size in a single pass.
+        let content_size = items
+            .iter()
+            .map(|(k, v)| k.encode_size() + v.encode_size())
+            .sum();
+        let page = build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_sized_iter(
+                SliceIter::new(&items),
+                items.len(),
+                content_size,
+            ),
+        );
+        assert_eq!(page, expect);
+    }
+
+    /// Counts the items yielded by the inner iterator over all passes.
+    struct CountingIter<'a, I> {
+        inner: I,
+        nexts: &'a Cell<usize>,
+    }
+
+    impl<I: Iterator> Iterator for CountingIter<'_, I> {
+        type Item = I::Item;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let item = self.inner.next()?;
+            self.nexts.set(self.nexts.get() + 1);
+            Some(item)
+        }
+    }
+
+    impl<I: RewindableIterator> RewindableIterator for CountingIter<'_, I> {
+        fn rewind(&mut self) {
+            self.inner.rewind();
+        }
+    }
+
+    fn sized_items() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..1000u32)
+            .map(|i| (i.to_be_bytes().to_vec(), vec![b'v'; i as usize % 7]))
+            .collect()
+    }
+
+    fn content_size(items: &[(&[u8], &[u8])]) -> usize {
+        items.iter().map(|(k, v)| k.encode_size() + v.encode_size()).sum()
+    }
+
+    #[test]
+    fn sorted_page_with_sized_iter() {
+        let items = sized_items();
+        let items: Vec<_> = items.iter().map(|(k, v)| (k.as_slice(), v.as_slice())).collect();
+        let content_size = content_size(&items);
+
+        let nexts = Cell::new(0);
+        let iter = CountingIter { inner: SliceIter::new(&items), nexts: &nexts };
+        let expect = build_page(SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_iter(iter));
+        assert_eq!(nexts.get(), 2 * items.len());
+
+        // Produces the same page without the sizing pass.
+        let nexts = Cell::new(0);
+        let iter = CountingIter { inner: SliceIter::new(&items), nexts: &nexts };
+        let page = build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_sized_iter(iter, items.len(), content_size),
+        );
+        assert_eq!(nexts.get(), items.len());
+        assert_eq!(page, expect);
+    }
+
+    #[test]
+    #[should_panic(expected = "items don't fill the declared size")]
+    fn sorted_page_with_over_declared_size() {
+        let items = sized_items();
+        let items: Vec<_> = items.iter().map(|(k, v)| (k.as_slice(), v.as_slice())).collect();
+        let content_size = content_size(&items);
+        build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_sized_iter(
+                SliceIter::new(&items),
+                items.len(),
+                content_size + 1,
+            ),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "items exceed the declared size")]
+    fn sorted_page_with_under_declared_size() {
+        let items = sized_items();
+        let items: Vec<_> = items.iter().map(|(k, v)| (k.as_slice(), v.as_slice())).collect();
+        let content_size = content_size(&items);
+        build_page(
+            SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_sized_iter(
+                SliceIter::new(&items),
+                items.len() - 1,
+                content_size,
+            ),
+        );
+    }
+
+    #[test]
+    fn sorted_page_iter_seek_to_first_and_last() {
+        let data: Vec<(&[u8], &[u8])> = vec![
+            (b"a".as_slice(), b"1".as_slice()),
+            (b"b".as_slice(), b"2".as_slice()),
+            (b"c".as_slice(), b"3".as_slice()),
+        ];
+        let buf = build_page(SortedPageBuilder::new(PageTier::Leaf, PageKind::Data).with_slice(&data));
+
+        let mut iter = SortedPageIter::<&[u8], &[u8]>::new(SortedPageRef::new(buf.as_ref().into()));
+        iter.seek_to_last();
+        assert_eq!(iter.next_back(), Some(data[2]));
+        assert_eq!(iter.next_back(), None);
+        iter.seek_to_last();
+        assert_eq!(iter.next(), Some(data[2]));
+        assert_eq!(iter.next(), None);
+
+        iter.seek_to_first();
+        assert_eq!(iter.next(), Some(data[0]));
+        assert_eq!(iter.next_back(), Some(data[2]));
+        assert_eq!(iter.next_back(), Some(data[1]));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+}
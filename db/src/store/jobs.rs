@@ -0,0 +1,562 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Result};
+use tokio::sync::{oneshot, watch, Notify};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+
+use crate::error::Error;
+use crate::utils::trace::{log_info, log_warn};
+
+/// The priority class of a background job.
+///
+/// When a slot is freed, it is given to the waiting jobs of a higher class
+/// first. Jobs are shut down in the reverse order, `Idle` first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum JobClass {
+    /// Jobs that writes depend on, e.g. flush.
+    Critical = 0,
+    /// e.g. space reclamation and compaction.
+    Normal = 1,
+    /// e.g. cache warmup and stats snapshots.
+    Idle = 2,
+}
+
+impl JobClass {
+    const ALL: [JobClass; 3] = [JobClass::Critical, JobClass::Normal, JobClass::Idle];
+}
+
+/// When a job runs.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Trigger {
+    /// Runs every interval, and whenever it is triggered.
+    Interval(Duration),
+    /// Runs only when it is triggered by [`JobTrigger::trigger`].
+    Event,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum JobState {
+    /// Waiting for the next trigger.
+    Idle,
+    /// Triggered, waiting for a free slot.
+    Pending,
+    Running,
+    /// Cancelled, it never runs again.
+    Stopped,
+}
+
+/// A row of the job table.
+#[derive(Clone, Debug)]
+pub(crate) struct JobInfo {
+    pub(crate) name: &'static str,
+    pub(crate) class: JobClass,
+    pub(crate) state: JobState,
+    pub(crate) runs: u64,
+    pub(crate) last_run: Option<SystemTime>,
+    pub(crate) last_duration: Option<Duration>,
+    pub(crate) last_error: Option<String>,
+}
+
+/// A token to cancel a running job cooperatively.
+///
+/// A job should check it at its await points and return early once it is
+/// cancelled.
+#[derive(Clone)]
+pub(crate) struct CancelToken(watch::Receiver<bool>);
+
+impl CancelToken {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until the token is cancelled.
+    pub(crate) async fn cancelled(&self) {
+        let mut rx = self.0.clone();
+        while !*rx.borrow_and_update() {
+            if rx.changed().await.is_err() {
+                // The scheduler is gone.
+                return;
+            }
+        }
+    }
+}
+
+/// Triggers a job to run as soon as a slot is free.
+///
+/// Triggers are coalesced, triggering a pending job again is a no-op.
+#[derive(Clone)]
+pub(crate) struct JobTrigger(Arc<Notify>);
+
+impl JobTrigger {
+    pub(crate) fn trigger(&self) {
+        self.0.notify_one();
+    }
+}
+
+/// Runs the background jobs of a store, e.g. flush, space reclamation, WAL
+/// purge and stats snapshots.
+///
+/// Every job is a named loop driven by its [`Trigger`]. At most
+/// `max_running` jobs run at the same time, and each class may be further
+/// limited by [`Self::set_class_limit`]. Jobs are cancelled cooperatively
+/// with a [`CancelToken`], and [`Self::shutdown`] stops them class by class.
+pub(crate) struct JobScheduler {
+    shared: Arc<Shared>,
+    tasks: Mutex<Option<Vec<Task>>>,
+}
+
+struct Task {
+    index: usize,
+    class: JobClass,
+    cancel: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+struct Shared {
+    slots: Mutex<Slots>,
+    jobs: Mutex<Vec<JobInfo>>,
+}
+
+struct Slots {
+    max_running: usize,
+    class_limits: [usize; 3],
+    running: [usize; 3],
+    waiters: [VecDeque<oneshot::Sender<()>>; 3],
+}
+
+impl JobScheduler {
+    pub(crate) fn new(max_running: usize) -> Self {
+        let max_running = max_running.max(1);
+        Self {
+            shared: Arc::new(Shared {
+                slots: Mutex::new(Slots {
+                    max_running,
+                    class_limits: [max_running; 3],
+                    running: [0; 3],
+                    waiters: Default::default(),
+                }),
+                jobs: Mutex::default(),
+            }),
+            tasks: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    /// Limits the number of running jobs of `class`.
+    pub(crate) fn set_class_limit(&self, class: JobClass, limit: usize) {
+        let mut slots = lock(&self.shared.slots);
+        slots.class_limits[class as usize] = limit.max(1);
+        slots.dispatch();
+    }
+
+    /// Registers a job and starts its loop.
+    ///
+    /// The job is called with a fresh clone of its cancel token on every run.
+    /// An error fails only that run, it is recorded in the job table.
+    /// Returns [`Error::Closed`] if the scheduler is shut down.
+    pub(crate) fn register<F, Fut>(
+        &self,
+        name: &'static str,
+        class: JobClass,
+        trigger: Trigger,
+        job: F,
+    ) -> Result<JobTrigger>
+    where
+        F: FnMut(CancelToken) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let mut tasks = lock(&self.tasks);
+        let Some(tasks) = tasks.as_mut() else {
+            bail!(Error::Closed);
+        };
+        let index = {
+            let mut jobs = lock(&self.shared.jobs);
+            jobs.push(JobInfo {
+                name,
+                class,
+                state: JobState::Idle,
+                runs: 0,
+                last_run: None,
+                last_duration: None,
+                last_error: None,
+            });
+            jobs.len() - 1
+        };
+        let (cancel, token) = watch::channel(false);
+        let notify = Arc::new(Notify::new());
+        let handle = tokio::spawn(run_job(
+            self.shared.clone(),
+            index,
+            class,
+            trigger,
+            notify.clone(),
+            CancelToken(token),
+            job,
+        ));
+        tasks.push(Task {
+            index,
+            class,
+            cancel,
+            handle,
+        });
+        Ok(JobTrigger(notify))
+    }
+
+    /// Returns the job table, in the order of registration.
+    pub(crate) fn jobs(&self) -> Vec<JobInfo> {
+        lock(&self.shared.jobs).clone()
+    }
+
+    /// Cancels all jobs and waits for them to stop, `Idle` jobs first and
+    /// `Critical` jobs last.
+    ///
+    /// Jobs still running at `deadline` are aborted and an error is returned.
+    pub(crate) async fn shutdown(&self, deadline: Duration) -> Result<()> {
+        let Some(mut tasks) = lock(&self.tasks).take() else {
+            return Ok(());
+        };
+        let deadline = Instant::now() + deadline;
+        let mut aborted = Vec::new();
+        for class in JobClass::ALL.into_iter().rev() {
+            let (stopping, rest): (Vec<_>, Vec<_>) =
+                tasks.into_iter().partition(|task| task.class == class);
+            tasks = rest;
+            for task in &stopping {
+                task.cancel.send_replace(true);
+            }
+            for mut task in stopping {
+                if tokio::time::timeout_at(deadline, &mut task.handle).await.is_err() {
+                    task.handle.abort();
+                    let mut jobs = lock(&self.shared.jobs);
+                    jobs[task.index].state = JobState::Stopped;
+                    aborted.push(jobs[task.index].name);
+                }
+            }
+        }
+        if !aborted.is_empty() {
+            log_warn!(jobs = ?aborted, "abort background jobs");
+            bail!("background jobs {aborted:?} didn't stop before the deadline");
+        }
+        log_info!("background jobs are stopped");
+        Ok(())
+    }
+}
+
+impl Shared {
+    /// Waits for a free slot of `class`.
+    async fn acquire(self: &Arc<Self>, class: JobClass) -> Slot {
+        let rx = {
+            let mut slots = lock(&self.slots);
+            let c = class as usize;
+            // Don't overtake the waiting jobs of the same or higher classes.
+            if slots.fits(c) && slots.waiters[..=c].iter().all(VecDeque::is_empty) {
+                slots.running[c] += 1;
+                return Slot {
+                    shared: self.clone(),
+                    class,
+                };
+            }
+            let (tx, rx) = oneshot::channel();
+            slots.waiters[c].push_back(tx);
+            rx
+        };
+        let mut waiter = Waiter {
+            shared: self.clone(),
+            class,
+            rx: Some(rx),
+        };
+        let rx = waiter.rx.as_mut().unwrap();
+        // The sender is never dropped before it is sent.
+        let _ = rx.await;
+        waiter.rx = None;
+        Slot {
+            shared: self.clone(),
+            class,
+        }
+    }
+
+    fn release(&self, class: JobClass) {
+        let mut slots = lock(&self.slots);
+        slots.running[class as usize] -= 1;
+        slots.dispatch();
+    }
+
+    fn set_state(&self, index: usize, state: JobState) {
+        lock(&self.jobs)[index].state = state;
+    }
+}
+
+impl Slots {
+    fn fits(&self, class: usize) -> bool {
+        self.running.iter().sum::<usize>() < self.max_running
+            && self.running[class] < self.class_limits[class]
+    }
+
+    /// Gives the free slots to the waiting jobs, higher classes first.
+    fn dispatch(&mut self) {
+        for c in 0..JobClass::ALL.len() {
+            while self.fits(c) {
+                let Some(tx) = self.waiters[c].pop_front() else {
+                    break;
+                };
+                if tx.send(()).is_ok() {
+                    self.running[c] += 1;
+                }
+            }
+        }
+    }
+}
+
+/// A running slot, it is freed when dropped.
+struct Slot {
+    shared: Arc<Shared>,
+    class: JobClass,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.shared.release(self.class);
+    }
+}
+
+/// Frees the slot if it is given to a waiter that is cancelled before
+/// receiving it.
+struct Waiter {
+    shared: Arc<Shared>,
+    class: JobClass,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if rx.try_recv().is_ok() {
+                self.shared.release(self.class);
+            }
+        }
+    }
+}
+
+async fn run_job<F, Fut>(
+    shared: Arc<Shared>,
+    index: usize,
+    class: JobClass,
+    trigger: Trigger,
+    notify: Arc<Notify>,
+    cancel: CancelToken,
+    mut job: F,
+) where
+    F: FnMut(CancelToken) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let mut interval = match trigger {
+        Trigger::Interval(period) => {
+            let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            Some(interval)
+        }
+        Trigger::Event => None,
+    };
+    loop {
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            _ = notify.notified() => {}
+            _ = tick(&mut interval) => {}
+        }
+        shared.set_state(index, JobState::Pending);
+        let slot = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            slot = shared.acquire(class) => slot,
+        };
+        shared.set_state(index, JobState::Running);
+        let last_run = SystemTime::now();
+        let start = Instant::now();
+        let result = job(cancel.clone()).await;
+        drop(slot);
+
+        let mut jobs = lock(&shared.jobs);
+        let info = &mut jobs[index];
+        info.state = JobState::Idle;
+        info.runs += 1;
+        info.last_run = Some(last_run);
+        info.last_duration = Some(start.elapsed());
+        info.last_error = result.err().map(|err| format!("{err:#}"));
+        if info.last_error.is_some() {
+            log_warn!(job = info.name, err = ?info.last_error, "background job failed");
+        }
+    }
+    shared.set_state(index, JobState::Stopped);
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn wait_for(scheduler: &JobScheduler, index: usize, f: impl Fn(&JobInfo) -> bool) {
+        while !f(&scheduler.jobs()[index]) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn register_and_job_table() {
+        let scheduler = JobScheduler::new(4);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<oneshot::Sender<()>>();
+        let mut fail = false;
+        let trigger = scheduler
+            .register("flush", JobClass::Critical, Trigger::Event, move |_| {
+                let (done_tx, done_rx) = oneshot::channel();
+                tx.send(done_tx).unwrap();
+                fail = !fail;
+                let fail = fail;
+                async move {
+                    done_rx.await?;
+                    if fail {
+                        bail!("no space");
+                    }
+                    Ok(())
+                }
+            })
+            .unwrap();
+        scheduler
+            .register("stats", JobClass::Idle, Trigger::Interval(Duration::from_millis(1)), |_| async {
+                Ok(())
+            })
+            .unwrap();
+
+        let jobs = scheduler.jobs();
+        assert_eq!(jobs[0].name, "flush");
+        assert_eq!(jobs[0].state, JobState::Idle);
+        assert_eq!(jobs[0].runs, 0);
+        // Interval jobs run without triggers.
+        wait_for(&scheduler, 1, |job| job.runs >= 2).await;
+
+        trigger.trigger();
+        let done = rx.recv().await.unwrap();
+        wait_for(&scheduler, 0, |job| job.state == JobState::Running).await;
+        done.send(()).unwrap();
+        wait_for(&scheduler, 0, |job| job.runs == 1).await;
+        let job = &scheduler.jobs()[0];
+        assert_eq!(job.state, JobState::Idle);
+        assert_eq!(job.last_error.as_deref(), Some("no space"));
+        assert!(job.last_run.is_some() && job.last_duration.is_some());
+
+        trigger.trigger();
+        rx.recv().await.unwrap().send(()).unwrap();
+        wait_for(&scheduler, 0, |job| job.runs == 2).await;
+        assert_eq!(scheduler.jobs()[0].last_error, None);
+
+        scheduler.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert!(scheduler.jobs().iter().all(|job| job.state == JobState::Stopped));
+        assert!(scheduler
+            .register("late", JobClass::Normal, Trigger::Event, |_| async { Ok(()) })
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn idle_job_waits_for_critical_jobs() {
+        let scheduler = JobScheduler::new(2);
+        scheduler.set_class_limit(JobClass::Idle, 1);
+        let release = Arc::new(Notify::new());
+        let mut triggers = Vec::new();
+        for name in ["flush-1", "flush-2"] {
+            let release = release.clone();
+            let trigger = scheduler
+                .register(name, JobClass::Critical, Trigger::Event, move |_| {
+                    let release = release.clone();
+                    async move {
+                        release.notified().await;
+                        Ok(())
+                    }
+                })
+                .unwrap();
+            triggers.push(trigger);
+        }
+        let warmup = scheduler
+            .register("warmup", JobClass::Idle, Trigger::Event, |_| async { Ok(()) })
+            .unwrap();
+
+        for trigger in &triggers {
+            trigger.trigger();
+        }
+        wait_for(&scheduler, 0, |job| job.state == JobState::Running).await;
+        wait_for(&scheduler, 1, |job| job.state == JobState::Running).await;
+        warmup.trigger();
+        wait_for(&scheduler, 2, |job| job.state == JobState::Pending).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(scheduler.jobs()[2].state, JobState::Pending);
+        assert_eq!(scheduler.jobs()[2].runs, 0);
+
+        release.notify_one();
+        wait_for(&scheduler, 2, |job| job.runs == 1).await;
+        release.notify_one();
+        scheduler.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancel_running_job() {
+        let scheduler = JobScheduler::new(4);
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        for (name, class) in [
+            ("flush", JobClass::Critical),
+            ("reclaim", JobClass::Normal),
+            ("warmup", JobClass::Idle),
+        ] {
+            let stopped = stopped.clone();
+            scheduler
+                .register(name, class, Trigger::Interval(Duration::from_millis(1)), move |token| {
+                    let stopped = stopped.clone();
+                    async move {
+                        // Blocks at an await point until cancelled.
+                        token.cancelled().await;
+                        assert!(token.is_cancelled());
+                        stopped.lock().unwrap().push(name);
+                        Ok(())
+                    }
+                })
+                .unwrap();
+        }
+        for index in 0..3 {
+            wait_for(&scheduler, index, |job| job.state == JobState::Running).await;
+        }
+        scheduler.shutdown(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(*stopped.lock().unwrap(), vec!["warmup", "reclaim", "flush"]);
+        for job in scheduler.jobs() {
+            assert_eq!(job.state, JobState::Stopped);
+            assert_eq!(job.runs, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn abort_at_deadline() {
+        let scheduler = JobScheduler::new(4);
+        scheduler
+            .register("stuck", JobClass::Normal, Trigger::Interval(Duration::from_millis(1)), |_| {
+                std::future::pending()
+            })
+            .unwrap();
+        wait_for(&scheduler, 0, |job| job.state == JobState::Running).await;
+        let err = scheduler.shutdown(Duration::from_millis(10)).await.unwrap_err();
+        assert!(err.to_string().contains("stuck"), "{err}");
+        assert_eq!(scheduler.jobs()[0].state, JobState::Stopped);
+    }
+}
@@ -1,29 +1,50 @@
 use std::{fs, io::ErrorKind, path::PathBuf, usize};
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Context, Result};
 use prost::Message;
 use tokio::fs::{create_dir_all, File, metadata, OpenOptions, read_dir, remove_file, rename,};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, SeekFrom};
 use crate::error::Error;
 use crate::store::meta::VersionEdit;
+use crate::utils::histogram::{Histogram, HistogramSnapshot};
+use crate::utils::trace::{log_debug, log_info, log_warn};
 
 
 const CURRENT_FILE_NAME: &str = "CURRENT";
 const MANIFEST_FILE_NAME: &str = "MANIFEST";
 const TEMPLE_SUFFIX: &str = "tmpdb";
 const MAX_MANIFEST_SIZE: u64 = 128 << 20; // 128 MiB
+const SNAPSHOT_EVERY_EDITS: u64 = 10_000;
 
 pub(crate) struct Manifest {
     base: PathBuf,
     base_dir: Option<File>,
     max_file_size: u64,
+    snapshot_every_edits: u64,
     next_file_id: u32,
 
     current_file_num: Option<u32>,
     current_writer: Option<ManifestWriter>,
+    record_latency: Histogram,
+
+    #[cfg(test)]
+    num_syncs: usize,
+    #[cfg(test)]
+    num_dir_syncs: usize,
+}
+
+/// 重放 manifest 的统计
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ReplayStats {
+    /// 重放的 version edit 数量
+    pub(crate) edits: u64,
+    /// 解码的字节数
+    pub(crate) bytes: u64,
 }
 
 struct ManifestWriter {
     current_file_size: u64,
+    /// 快照之后写入的 edit 数量
+    num_edits: u64,
     current_writer: File,
 }
 
@@ -37,12 +58,25 @@ impl Manifest {
             base,
             base_dir: None,
             max_file_size: MAX_MANIFEST_SIZE,
+            snapshot_every_edits: SNAPSHOT_EVERY_EDITS,
             next_file_id: 0,
             current_file_num: Default::default(),
             current_writer: None,
+            record_latency: Histogram::new(),
+            #[cfg(test)]
+            num_syncs: 0,
+            #[cfg(test)]
+            num_dir_syncs: 0,
         };
         manifest.create_base_dir_if_not_exist().await?;
+        // Directories can't be opened as files on Windows, the entries are
+        // persisted by the filesystem there.
+        #[cfg(unix)]
+        {
+            manifest.base_dir = Some(File::open(&manifest.base).await.context("open base dir")?);
+        }
         manifest.current_file_num = manifest.load_current().await?;
+        log_info!(base = ?manifest.base, current = ?manifest.current_file_num, "open manifest");
         // 清理过期文件
         // TODO: 清理到异步任务中
         manifest.cleanup_obsolete_files().await?;
@@ -53,17 +87,25 @@ impl Manifest {
         match create_dir_all(&self.base).await {
             Ok(_) => {}
             Err(err) if err.kind() == ErrorKind::AlreadyExists => {
-                let raw_metadata = fs::metadata(&self.base)?;
+                let raw_metadata = fs::metadata(&self.base)
+                    .with_context(|| format!("stat base dir {}", self.base.display()))?;
 
                 if !raw_metadata.is_dir() {
-                    panic!("base dir is not a dir")
+                    bail!(Error::NotDirectory(self.base.clone()));
                 }
             }
-            Err(err) => panic!("open base dir fail, {}", err),
+            Err(err) => {
+                return Err(err).with_context(|| format!("create base dir {}", self.base.display()))
+            }
         }
         Ok(())
     }
 
+    /// 设置 [`Self::need_snapshot`] 的 edit 数量阈值
+    pub(super) fn set_snapshot_every_edits(&mut self, edits: u64) {
+        self.snapshot_every_edits = edits;
+    }
+
     pub(super) fn reset_next_file_id(&mut self, next_id: u32) {
         self.next_file_id = next_id;
     }
@@ -84,61 +126,80 @@ impl Manifest {
         ve: VersionEdit,
         version_snapshot: impl FnOnce() -> VersionEdit,
     ) -> Result<()> {
-        let mut current = self.current_writer.take();
-        let mut file_num = self.current_file_num.as_ref().unwrap_or(&0).to_owned();
+        self.record_batch(vec![ve], |_| version_snapshot()).await
+    }
 
-        let rolled_path = if current.is_none()
-            || current.as_ref().unwrap().current_file_size > self.max_file_size
+    /// 将一批 version_edit 连续写入 manifest 文件, 只 fsync 一次.
+    ///
+    /// 如果在写入过程中文件大小超过 max_file_size, 会在下一个 edit 之前滚动到新文件,
+    /// 一批 edit 最多滚动一次. 滚动时 `version_snapshot` 会收到本批中已经写入旧文件的
+    /// edit, 需要返回包含这些 edit 的快照.
+    ///
+    /// 崩溃时只有完整写入的 edit 会被恢复.
+    pub(crate) async fn record_batch(
+        &mut self,
+        edits: Vec<VersionEdit>,
+        version_snapshot: impl FnOnce(&[VersionEdit]) -> VersionEdit,
+    ) -> Result<()> {
+        let start = std::time::Instant::now();
+        // 新文件的目录项必须先于引用它的 edit 持久化, 否则崩溃后 manifest 可能引用一个
+        // 不存在的文件. 同一批 edit 新增的文件只需要同步一次目录.
+        // 这里只覆盖 manifest 目录中的文件, 其他数据目录中的文件由 `build_file` 同步.
+        if edits
+            .iter()
+            .any(|ve| ve.file_stream.as_ref().is_some_and(|s| !s.new_files.is_empty()))
         {
+            self.sync_base_dir().await?;
+        }
 
-            file_num += 1;
-            let path = self
-                .base
-                .join(format!("{}_{}", MANIFEST_FILE_NAME, file_num));
-            let current_writer = File::create(&path).await?;
-            current = Some(ManifestWriter {
-                current_file_size: 0,
-                current_writer,
-            });
-            Some(path)
-        } else {
-            None
-        };
+        // 写入失败时不再复用当前文件, 下次写入会滚动到新文件
+        let mut current = self.current_writer.take();
+        let mut file_num = self.current_file_num.unwrap_or(0);
+        let mut version_snapshot = Some(version_snapshot);
+        let mut rolled_path = None;
+
+        for (i, ve) in edits.iter().enumerate() {
+            let need_roll = rolled_path.is_none()
+                && current
+                    .as_ref()
+                    .is_none_or(|c| c.current_file_size > self.max_file_size);
+            if need_roll {
+                file_num += 1;
+                // 先写入快照版本
+                let base_snapshot = (version_snapshot.take().unwrap())(&edits[..i]);
+                let (writer, path) = self.create_file(file_num, &base_snapshot).await?;
+                current = Some(writer);
+                rolled_path = Some(path);
+            }
 
-        let mut current = current.unwrap();
-        let written = if rolled_path.is_some() {  // 说明需要进行滚动了
-            // TODO: remove new created file when write fail.
-            let base_snapshot = version_snapshot();
-            // 先写入快照版本
-            let base_written = VersionEditEncoder(base_snapshot)
-                .encode(&mut current.current_writer)
-                .await?;
             // 再写具体数据
-            match VersionEditEncoder(ve)
-                .encode(&mut current.current_writer)
-                .await
-            {
-                Ok(record_written) => base_written + record_written,
+            let writer = current.as_mut().unwrap();
+            match VersionEditEncoder(ve).encode(&mut writer.current_writer).await {
+                Ok(written) => {
+                    writer.current_file_size += written as u64;
+                    writer.num_edits += 1;
+                }
                 Err(err) => {
-                    // 因为是滚动下一个文件
-                    remove_file(rolled_path.as_ref().unwrap()).await?;
+                    // 新滚动的文件还没有被 CURRENT 引用, 可以直接删除
+                    if let Some(path) = rolled_path {
+                        remove_file(path).await?;
+                    }
                     return Err(err);
                 }
             }
-        } else {
-            // 继续写入
-            VersionEditEncoder(ve)
-                .encode(&mut current.current_writer)
-                .await?
-        } as u64;
+        }
 
+        let Some(current) = current else {
+            return Ok(());
+        };
         if rolled_path.is_some() {
             current
                 .current_writer
                 .sync_all()
                 .await
-                .expect("sync new manifest file fail");
+                .context("sync new manifest file")?;
             self.set_current(file_num).await?;
+            log_info!(file_num, file_size = current.current_file_size, "roll manifest");
             // TODO: notify cleaner previous manifest + size, so it can be delete when need.
             self.current_file_num = Some(file_num);
         } else {
@@ -146,33 +207,114 @@ impl Manifest {
                 .current_writer
                 .sync_all()
                 .await
-                .expect("sync manifest data fail");
+                .context("sync manifest data")?;
+        }
+        #[cfg(test)]
+        {
+            self.num_syncs += 1;
         }
-
-        current.current_file_size += written;
 
         self.current_writer = Some(current);
+        self.record_latency.record(start.elapsed());
 
         Ok(())
     }
 
+    /// 返回成功写入一批 edit (包括 fsync) 的延迟分布
+    pub(crate) fn record_latency(&self) -> HistogramSnapshot {
+        self.record_latency.snapshot()
+    }
+
+    /// 当前文件在快照之后累积的 edit 超过阈值时返回 true.
+    ///
+    /// 大量很小的 edit 不会触发按大小滚动, 但会拖慢恢复时的重放, 后台任务应当在
+    /// 这时调用 [`Self::snapshot`].
+    pub(crate) fn need_snapshot(&self) -> bool {
+        self.current_writer
+            .as_ref()
+            .is_some_and(|w| w.num_edits >= self.snapshot_every_edits)
+    }
+
+    /// 立即滚动到一个只包含 `version_snapshot` 的新文件, 不论当前文件的大小.
+    ///
+    /// 调用方需要保证快照包含了所有已经写入的 edit.
+    pub(crate) async fn snapshot(
+        &mut self,
+        version_snapshot: impl FnOnce() -> VersionEdit,
+    ) -> Result<()> {
+        self.current_writer = None;
+        let file_num = self.current_file_num.unwrap_or(0) + 1;
+        let (writer, path) = self.create_file(file_num, &version_snapshot()).await?;
+        if let Err(err) = writer.current_writer.sync_all().await {
+            remove_file(&path).await?;
+            return Err(err).context("sync new manifest file");
+        }
+        self.set_current(file_num).await?;
+        log_info!(file_num, "snapshot manifest");
+        self.current_file_num = Some(file_num);
+        self.current_writer = Some(writer);
+        Ok(())
+    }
+
+    /// 创建编号为 `file_num` 的 manifest 文件并写入快照, 失败时删除该文件
+    async fn create_file(
+        &self,
+        file_num: u32,
+        snapshot: &VersionEdit,
+    ) -> Result<(ManifestWriter, PathBuf)> {
+        let path = self
+            .base
+            .join(format!("{}_{}", MANIFEST_FILE_NAME, file_num));
+        let mut writer = ManifestWriter {
+            current_file_size: 0,
+            num_edits: 0,
+            current_writer: File::create(&path).await?,
+        };
+        match VersionEditEncoder(snapshot)
+            .encode(&mut writer.current_writer)
+            .await
+        {
+            Ok(written) => writer.current_file_size += written as u64,
+            Err(err) => {
+                remove_file(&path).await?;
+                return Err(err);
+            }
+        }
+        Ok((writer, path))
+    }
+
     // List current versions.
     // the caller can recovery Versions by apply each version_edits.
     pub(crate) async fn list_versions(&self) -> Result<Vec<VersionEdit>> {
-        Ok(if let Some(current_file) = self.current_file_num {
+        let mut ves = Vec::new();
+        self.replay(|ve| {
+            ves.push(ve);
+            Ok(())
+        })
+        .await?;
+        Ok(ves)
+    }
+
+    /// 按顺序逐条解码当前 manifest 中的 version edit 并交给 `f`, 不会一次性加载所有
+    /// edit, 恢复时的内存占用只与 `f` 构建的状态有关.
+    pub(crate) async fn replay<F>(&self, mut f: F) -> Result<ReplayStats>
+        where
+            F: FnMut(VersionEdit) -> Result<()>,
+    {
+        let mut stats = ReplayStats::default();
+        if let Some(current_file) = self.current_file_num {
             let path = self
                 .base
                 .join(format!("{}_{}", MANIFEST_FILE_NAME, current_file));
             let reader = File::open(path).await?;
             let mut decoder = VersionEditDecoder::new(reader);
-            let mut ves = Vec::new();
             while let Some(ve) = decoder.next_record().await.map_err(|_| Error::Corrupted)? {
-                ves.push(ve)
+                stats.edits += 1;
+                f(ve)?;
             }
-            ves
-        } else {
-            vec![]
-        })
+            stats.bytes = decoder.offset;
+        }
+        Ok(stats)
     }
 
     async fn load_current(&self) -> Result<Option<u32 /* file_num */>> {
@@ -182,7 +324,7 @@ impl Manifest {
         {
             Ok(f) => f,
             Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
-            Err(_) => panic!("read current meet error"),
+            Err(err) => return Err(err).context("open CURRENT"),
         };
         let mut file_num_bytes = vec![0u8; core::mem::size_of::<u32>()];
         curr_file_reader
@@ -196,7 +338,7 @@ impl Manifest {
         Ok(Some(file_num))
     }
 
-    async fn set_current(&self, file_num: u32) -> Result<()> {
+    async fn set_current(&mut self, file_num: u32) -> Result<()> {
         //先建一个临时文件， 之后再修改名称
         let tmp_path = self
             .base
@@ -208,7 +350,7 @@ impl Manifest {
             tmp_file
                 .sync_all()
                 .await
-                .expect("sync tmp current file fail");
+                .context("sync tmp current file")?;
         }
 
         match rename(&tmp_path, self.base.join(CURRENT_FILE_NAME))
@@ -221,6 +363,18 @@ impl Manifest {
                 Err(Error::Corrupted)
             }
         }?;
+        // 同时持久化新 manifest 文件和 CURRENT 的目录项
+        self.sync_base_dir().await
+    }
+
+    async fn sync_base_dir(&mut self) -> Result<()> {
+        if let Some(dir) = &self.base_dir {
+            dir.sync_all().await.context("sync base dir")?;
+        }
+        #[cfg(test)]
+        {
+            self.num_dir_syncs += 1;
+        }
         Ok(())
     }
 
@@ -258,6 +412,7 @@ impl Manifest {
         }
 
         for path in wait_remove_paths {
+            log_debug!(path = ?path, "remove obsolete manifest file");
             remove_file(path).await?;
         }
 
@@ -266,9 +421,9 @@ impl Manifest {
 
 }
 
-struct VersionEditEncoder(VersionEdit);
+struct VersionEditEncoder<'a>(&'a VersionEdit);
 
-impl VersionEditEncoder {
+impl VersionEditEncoder<'_> {
     async fn encode(&self, w: &mut File) -> Result<usize> {
         let bytes = self.0.encode_to_vec();
         w.write_all(&bytes.len().to_le_bytes()).await?;
@@ -297,7 +452,10 @@ impl VersionEditDecoder {
                 .read_exact(&mut len_bytes)
                 .await
             {
-                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    log_debug!(offset, "reach the end of manifest");
+                    return Ok(None);
+                }
                 e @ Err(_) => e?,
                 _ => {0}
             };
@@ -311,11 +469,15 @@ impl VersionEditDecoder {
         let ve = {
             let mut ve_bytes = vec![0u8; len as usize];
             self.reader.seek(SeekFrom::Start(offset)).await?;
-            self.reader
-                .read_exact(&mut ve_bytes)
-                .await?;
-            // error
-            println!("{:?}", ve_bytes);
+            match self.reader.read_exact(&mut ve_bytes).await {
+                // 崩溃时最后一条记录可能没有完整写入
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                    log_warn!(offset, len, "ignore the torn record at the end of manifest");
+                    return Ok(None);
+                }
+                e @ Err(_) => e?,
+                _ => 0,
+            };
             VersionEdit::decode(ve_bytes.as_slice()).map_err(|_| Error::Corrupted)?
         };
         self.offset = offset + len;
@@ -334,6 +496,18 @@ mod tests {
         ids.into_iter().map(Into::into).collect()
     }
 
+    #[tokio::test]
+    async fn test_open_invalid_base() {
+        let base = tempdir::TempDir::new("manifest_invalid_base").unwrap();
+        let file = base.path().join("file");
+        std::fs::write(&file, b"").unwrap();
+
+        let err = Manifest::open(&file).await.err().unwrap();
+        assert!(matches!(err.downcast_ref(), Some(Error::NotDirectory(path)) if *path == file));
+        // A path below a file can't be created either.
+        assert!(Manifest::open(file.join("sub")).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_cleanup_when_restart() {
         let base = tempdir::TempDir::new("curr_test_restart").unwrap();
@@ -555,4 +729,270 @@ mod tests {
             assert_eq!(versions.len(), 4);
         }
     }
+
+    /// Applies an edit to the live file set, the same way recovery does.
+    fn apply_edit(files: &mut Vec<NewFile>, ve: &VersionEdit) {
+        let edit = ve.file_stream.as_ref().unwrap();
+        files.extend_from_slice(&edit.new_files);
+        files.retain(|f| !edit.deleted_files.contains(&f.id));
+    }
+
+    async fn recover_files(manifest: &Manifest) -> Vec<NewFile> {
+        let mut files = Vec::new();
+        for ve in manifest.list_versions().await.unwrap() {
+            apply_edit(&mut files, &ve);
+        }
+        files
+    }
+
+    /// 模拟崩溃恢复: 随机写入 version edit, 不做任何关闭操作直接 drop,
+    /// 重新打开后检查每一个已确认的 edit 都能够被恢复.
+    async fn recover_after_crash(max_file_size: u64) {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let base = tempdir::TempDir::new("curr_test_recover").unwrap();
+        let mut rng = StdRng::seed_from_u64(max_file_size);
+        let mut live: Vec<NewFile> = Vec::new();
+        let mut next_id = 0u32;
+
+        for _ in 0..5 {
+            let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+            manifest.max_file_size = max_file_size;
+
+            assert_eq!(recover_files(&manifest).await, live);
+
+            for _ in 0..rng.gen_range(1..20) {
+                let mut deleted_files = Vec::new();
+                if !live.is_empty() && rng.gen_bool(0.5) {
+                    deleted_files.push(live[rng.gen_range(0..live.len())].id);
+                }
+                let ve = VersionEdit {
+                    file_stream: Some(StreamEdit {
+                        new_files: new_files(vec![next_id]),
+                        deleted_files,
+                    }),
+                };
+                next_id += 1;
+                let snapshot = live.clone();
+                manifest
+                    .record_version_edit(ve.to_owned(), move || VersionEdit {
+                        file_stream: Some(StreamEdit {
+                            new_files: snapshot,
+                            deleted_files: vec![],
+                        }),
+                    })
+                    .await
+                    .unwrap();
+                apply_edit(&mut live, &ve);
+            }
+            // Crash without any clean close.
+            drop(manifest);
+        }
+
+        let manifest = Manifest::open(base.as_ref()).await.unwrap();
+        assert_eq!(recover_files(&manifest).await, live);
+    }
+
+    #[tokio::test]
+    async fn test_recover_after_crash() {
+        recover_after_crash(MAX_MANIFEST_SIZE).await;
+    }
+
+    #[tokio::test]
+    async fn test_recover_after_crash_with_roll() {
+        recover_after_crash(64).await;
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_before_new_files() {
+        let base = tempdir::TempDir::new("curr_test_sync_dir").unwrap();
+        let edit = |new: Vec<u32>, deleted: Vec<u32>| VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: new_files(new),
+                deleted_files: deleted,
+            }),
+        };
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        // Once for the new file, once for the rolled manifest and CURRENT.
+        manifest.record_version_edit(edit(vec![1], vec![]), VersionEdit::default).await.unwrap();
+        assert_eq!(manifest.num_dir_syncs, 2);
+        // Only deletes files, no new directory entry to sync.
+        manifest.record_version_edit(edit(vec![], vec![1]), VersionEdit::default).await.unwrap();
+        assert_eq!(manifest.num_dir_syncs, 2);
+        // New files in one batch are synced together.
+        manifest
+            .record_batch(vec![edit(vec![2], vec![]), edit(vec![3], vec![])], |_| {
+                VersionEdit::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(manifest.num_dir_syncs, 3);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_every_edits() {
+        let base = tempdir::TempDir::new("curr_test_snapshot").unwrap();
+        let snapshot_of = |live: &[NewFile]| VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: live.to_vec(),
+                deleted_files: vec![],
+            }),
+        };
+
+        let mut live = Vec::new();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        manifest.set_snapshot_every_edits(100);
+        for id in 0..250u32 {
+            let ve = VersionEdit {
+                file_stream: Some(StreamEdit {
+                    new_files: new_files(vec![id]),
+                    deleted_files: id.checked_sub(10).into_iter().collect(),
+                }),
+            };
+            let snapshot = snapshot_of(&live);
+            manifest.record_version_edit(ve.clone(), || snapshot).await.unwrap();
+            apply_edit(&mut live, &ve);
+            // What the maintenance job does.
+            if manifest.need_snapshot() {
+                manifest.snapshot(|| snapshot_of(&live)).await.unwrap();
+            }
+        }
+        // The size threshold is never reached, but the edit count is.
+        assert!(manifest.current_writer.as_ref().unwrap().current_file_size < MAX_MANIFEST_SIZE);
+        assert_eq!(manifest.current_file_num, Some(3));
+        drop(manifest);
+
+        let manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let mut recovered = Vec::new();
+        let stats = manifest
+            .replay(|ve| {
+                apply_edit(&mut recovered, &ve);
+                Ok(())
+            })
+            .await
+            .unwrap();
+        // The snapshot and the edits after it.
+        assert_eq!(stats.edits, 1 + 50);
+        assert_eq!(recovered, live);
+    }
+
+    #[tokio::test]
+    async fn test_record_batch() {
+        let base = tempdir::TempDir::new("curr_test_batch").unwrap();
+        let batch = |ids: std::ops::Range<u32>| -> Vec<VersionEdit> {
+            ids.map(|id| VersionEdit {
+                file_stream: Some(StreamEdit {
+                    new_files: new_files(vec![id]),
+                    deleted_files: id.checked_sub(50).into_iter().collect(),
+                }),
+            })
+            .collect()
+        };
+        let snapshot_of = |live: &[NewFile], written: &[VersionEdit]| {
+            let mut files = live.to_vec();
+            for ve in written {
+                apply_edit(&mut files, ve);
+            }
+            VersionEdit {
+                file_stream: Some(StreamEdit {
+                    new_files: files,
+                    deleted_files: vec![],
+                }),
+            }
+        };
+
+        let mut live = Vec::new();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let edits = batch(0..200);
+        manifest
+            .record_batch(edits.clone(), |written| snapshot_of(&live, written))
+            .await
+            .unwrap();
+        assert_eq!(manifest.num_syncs, 1);
+        assert_eq!(manifest.record_latency().count(), 1);
+        assert_eq!(manifest.current_file_num, Some(1));
+        edits.iter().for_each(|ve| apply_edit(&mut live, ve));
+        assert_eq!(recover_files(&manifest).await, live);
+
+        // Rolls in the middle of the batch.
+        manifest.max_file_size = manifest.current_writer.as_ref().unwrap().current_file_size + 64;
+        let edits = batch(200..400);
+        manifest
+            .record_batch(edits.clone(), |written| {
+                assert!(!written.is_empty() && written.len() < edits.len());
+                snapshot_of(&live, written)
+            })
+            .await
+            .unwrap();
+        assert_eq!(manifest.num_syncs, 2);
+        assert_eq!(manifest.current_file_num, Some(2));
+        edits.iter().for_each(|ve| apply_edit(&mut live, ve));
+        assert_eq!(recover_files(&manifest).await, live);
+        drop(manifest);
+
+        // A crash in the middle of the batch leaves a torn record at the end,
+        // only the fully written records are replayed.
+        let path = base.path().join(format!("{}_{}", MANIFEST_FILE_NAME, 2));
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 3]).unwrap();
+        let manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let versions = manifest.list_versions().await.unwrap();
+        let mut recovered = Vec::new();
+        versions.iter().for_each(|ve| apply_edit(&mut recovered, ve));
+        let mut expect = live.clone();
+        expect.pop();
+        expect.push(NewFile::from(349));
+        expect.sort();
+        recovered.sort();
+        assert_eq!(recovered, expect);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_trace_roll() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        /// Records the message of every event.
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl Visit for Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.lock().unwrap().push(format!("{value:?}"));
+                }
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &Attributes<'_>) -> Id {
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut self.clone());
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let base = tempdir::TempDir::new("manifest_trace").unwrap();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        manifest
+            .record_version_edit(VersionEdit::default(), VersionEdit::default)
+            .await
+            .unwrap();
+        let messages = recorder.0.lock().unwrap().clone();
+        assert!(messages.iter().any(|m| m == "open manifest"), "{messages:?}");
+        assert!(messages.iter().any(|m| m == "roll manifest"), "{messages:?}");
+    }
 }
@@ -10,6 +10,36 @@ pub(crate) struct NewFile {
     pub up1: u32,
     #[prost(uint32, tag = "3")]
     pub up2: u32,
+    /// The smallest lsn of the keys in the file, `None` if unknown.
+    #[prost(uint64, optional, tag = "4")]
+    pub min_lsn: Option<u64>,
+    /// The largest lsn of the keys in the file, `None` if unknown.
+    #[prost(uint64, optional, tag = "5")]
+    pub max_lsn: Option<u64>,
+    /// The files whose page groups are referenced by this file.
+    #[prost(uint32, repeated, tag = "6")]
+    pub referenced_files: Vec<u32>,
+    /// The wall-clock time the file was created, in microseconds since the
+    /// unix epoch.
+    #[prost(uint64, optional, tag = "7")]
+    pub created_at: Option<u64>,
+    /// How the file was created.
+    #[prost(enumeration = "FileSource", optional, tag = "8")]
+    pub source: Option<i32>,
+    /// The files whose pages were rewritten into this file.
+    #[prost(uint32, repeated, tag = "9")]
+    pub parent_files: Vec<u32>,
+}
+
+/// How a file was created.
+#[allow(unreachable_pub)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub(crate) enum FileSource {
+    Flush = 0,
+    Reclaim = 1,
+    ColdCompact = 2,
+    Ingest = 3,
 }
 
 /// A sequence of ordered files forms a stream.
@@ -40,6 +70,12 @@ mod convert {
                 id: file_id,
                 up1: file_id,
                 up2: file_id,
+                min_lsn: None,
+                max_lsn: None,
+                referenced_files: Vec::new(),
+                created_at: None,
+                source: None,
+                parent_files: Vec::new(),
             }
         }
     }
@@ -61,7 +97,13 @@ mod tests {
 
     #[test]
     fn version_edit_decode_and_encode() {
-        let new_files: Vec<NewFile> = vec![4, 5, 6].into_iter().map(Into::into).collect();
+        let mut new_files: Vec<NewFile> = vec![4, 5, 6].into_iter().map(Into::into).collect();
+        new_files[0].min_lsn = Some(10);
+        new_files[0].max_lsn = Some(20);
+        new_files[1].referenced_files = vec![1, 2];
+        new_files[2].created_at = Some(1_700_000_000_000_000);
+        new_files[2].set_source(FileSource::Reclaim);
+        new_files[2].parent_files = vec![1, 3];
         let edit = VersionEdit {
             file_stream: Some(StreamEdit {
                 new_files,
@@ -72,5 +114,8 @@ mod tests {
         let payload = edit.encode_to_vec();
         let new = VersionEdit::decode(payload.as_slice()).unwrap();
         assert_eq!(edit, new);
+        let new_files = &new.file_stream.unwrap().new_files;
+        assert_eq!(new_files[2].source(), FileSource::Reclaim);
+        assert_eq!(new_files[0].source, None);
     }
 }
@@ -1,7 +1,15 @@
+mod jobs;
 mod manifest;
-mod meta;
+pub(crate) mod meta;
 mod page_store;
 
+use crate::error::{Error, Result};
+use crate::file::constant::IO_BUFFER_SIZE;
+
+/// The maximum size of a key and a value together, well under the `u32`
+/// lengths and offsets used in pages.
+const MAX_RECORD_SIZE: usize = 1 << 30;
+
 /// Options to configure a page store.
 #[non_exhaustive]
 #[derive(Clone, Debug)]
@@ -24,6 +32,88 @@ pub struct Options {
     /// Default: false
     pub use_direct_io: bool,
 
+    /// The size of the buffer used to read and write page files. It must be a
+    /// power of two.
+    ///
+    /// A larger buffer issues fewer syscalls for sequential IO like flush,
+    /// compaction and bulk loading, but every open file holds one buffer, so
+    /// memory-constrained embedders may want a smaller one.
+    ///
+    /// Default: 8MB
+    pub io_buffer_size: usize,
+
+    /// If true, read page files with synchronous positioned reads instead of
+    /// going through tokio. This reduces overhead for tiny, single-threaded
+    /// embedders, but blocks the calling thread on IO.
+    ///
+    /// Not yet honored: there is no read path that picks a reader from the
+    /// options, so reads always go through tokio. `SyncFileReader` is the
+    /// reader it will select.
+    ///
+    /// Default: false
+    pub sync_reads: bool,
+
+    /// The maximum number of retries of a page file read that fails with a
+    /// transient error (interrupted, would block or timed out), e.g. on a
+    /// network filesystem. The backoff doubles from 1ms up to 100ms between
+    /// retries.
+    ///
+    /// Not yet honored: no file reader is opened from the options, so reads
+    /// use the default policy, which never retries. A reader built with
+    /// `RetryPolicy::new(io_max_retries)` applies it.
+    ///
+    /// Default: 0
+    pub io_max_retries: u32,
+
+    /// The maximum number of page files open at the same time, opens beyond
+    /// it wait for another file to be closed instead of failing with EMFILE.
+    /// Cached file readers keep their files open, so it should be larger
+    /// than `cache_file_reader_capacity`. Zero means unlimited.
+    ///
+    /// Not yet honored: the store doesn't build its shared `OpenFileLimiter`
+    /// from the options yet, so only the components given a limiter
+    /// explicitly are bounded.
+    ///
+    /// Default: 0
+    pub max_open_files: usize,
+
+    /// The maximum size of a key. Larger keys are rejected with
+    /// [`Error::KeyTooLarge`].
+    ///
+    /// Not yet honored: the table has no write path yet, so no record is
+    /// checked against it. Only [`Options::validate`] reads it.
+    ///
+    /// Default: 64KB
+    pub max_key_size: usize,
+
+    /// The maximum size of a value. Larger values are rejected with
+    /// [`Error::ValueTooLarge`].
+    ///
+    /// Keys and values are stored in pages with `u32` lengths and offsets, so
+    /// a key and a value together must not exceed 1GB.
+    ///
+    /// Not yet honored, like `max_key_size`.
+    ///
+    /// Default: 64MB
+    pub max_value_size: usize,
+
+    /// Values larger than this are stored out of line, in a blob region at the
+    /// end of the page file, and the page keeps only a pointer to them. This
+    /// keeps pages small and cache-friendly without a separate value log.
+    ///
+    /// Default: usize::MAX (all values are inline)
+    pub inline_value_threshold: usize,
+
+    /// The number of version edits after which the manifest is rewritten as
+    /// a fresh snapshot, even if it is still small. This bounds the edits
+    /// replayed on recovery.
+    ///
+    /// Not yet honored: nothing opens the manifest from the options, so it
+    /// always uses the default.
+    ///
+    /// Default: 10000
+    pub manifest_snapshot_every_edits: u64,
+
     /// If true, no space reclamation.
     ///
     /// Default: false
@@ -43,6 +133,15 @@ pub struct Options {
     /// Default: u64::MAX
     pub space_used_high: u64,
 
+    /// The maximum total size of the orphaned page files kept in the
+    /// `quarantine` directory. The oldest ones are deleted beyond it.
+    ///
+    /// Not yet honored: the quarantine is not created from the options, its
+    /// callers pass the size to `Quarantine::new` directly.
+    ///
+    /// Default: 1GB
+    pub max_quarantine_size: u64,
+
     /// Target file size for compaction.
     ///
     /// Default: 64MB
@@ -89,6 +188,46 @@ pub struct Options {
     /// Default: true
     pub prepopulate_cache_on_flush: bool,
 
+    /// The IO rate limit shared by flush, compaction and space reclamation, in
+    /// bytes per second. Flushes are never throttled but still consume the
+    /// budget, so compaction slows down to leave room for them.
+    ///
+    /// Not yet honored: flush and compaction don't run in this tree yet, and
+    /// no `RateLimiter` is created from this option.
+    ///
+    /// Default: 0 (unlimited)
+    pub background_io_bytes_per_sec: u64,
+
+    /// If true, a background job verifies the checksums of all live pages at
+    /// the pace of `background_io_bytes_per_sec`, to find silent disk
+    /// corruption before a read does.
+    ///
+    /// Not yet honored: the `Scrubber` exists, but there is no store to
+    /// schedule it as a background job.
+    ///
+    /// Default: false
+    pub enable_scrubbing: bool,
+
+    /// How the footers and meta blocks of the page files referenced by the
+    /// manifest are verified when the store is opened, see
+    /// [`VerifyFilesMode`].
+    ///
+    /// Not yet honored: nothing opens a store from the options yet, so
+    /// `verify_on_open` is never called with it.
+    ///
+    /// Default: [`VerifyFilesMode::Lazy`]
+    pub verify_files_on_open: VerifyFilesMode,
+
+    /// The maximum number of background jobs (flush, space reclamation, WAL
+    /// purge, stats snapshots, ...) running at the same time. Flush jobs are
+    /// given free slots first.
+    ///
+    /// Not yet honored: no `JobScheduler` is created from the options, the
+    /// store that would run the jobs is not in this tree yet.
+    ///
+    /// Default: 4
+    pub max_background_jobs: usize,
+
     /// Compression method during flush new file.
     /// include hot rewrite.
     ///
@@ -119,15 +258,28 @@ impl Default for Options {
             write_buffer_capacity: 128 << 20,
             max_write_buffers: 8,
             use_direct_io: false,
+            io_buffer_size: IO_BUFFER_SIZE,
+            sync_reads: false,
+            io_max_retries: 0,
+            max_open_files: 0,
+            max_key_size: 64 << 10,
+            max_value_size: 64 << 20,
+            inline_value_threshold: usize::MAX,
+            manifest_snapshot_every_edits: 10_000,
             disable_space_reclaiming: false,
             max_space_amplification_percent: 100,
             space_used_high: u64::MAX,
+            max_quarantine_size: 1 << 30,
             file_base_size: 64 << 20,
             cache_capacity: 8 << 20,
             cache_estimated_entry_charge: 8 << 10,
             cache_file_reader_capacity: 5000,
             cache_strict_capacity_limit: false,
             prepopulate_cache_on_flush: true,
+            background_io_bytes_per_sec: 0,
+            enable_scrubbing: false,
+            verify_files_on_open: VerifyFilesMode::Lazy,
+            max_background_jobs: 4,
             // compression_on_flush: Compression::SNAPPY,
             // compression_on_cold_compact: Compression::ZSTD,
             // page_checksum_type: ChecksumType::NONE,
@@ -136,6 +288,45 @@ impl Default for Options {
     }
 }
 
+impl Options {
+    /// Checks that the options are valid.
+    pub fn validate(&self) -> Result<()> {
+        if !self.io_buffer_size.is_power_of_two() {
+            return Err(Error::InvalidArgument);
+        }
+        if self.max_key_size.saturating_add(self.max_value_size) > MAX_RECORD_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Checks that a record fits in the key and value size limits, for the
+    /// write path to call before a record is buffered.
+    pub(crate) fn check_record_size(&self, key: &[u8], value: Option<&[u8]>) -> Result<()> {
+        if key.len() > self.max_key_size {
+            return Err(Error::KeyTooLarge);
+        }
+        if value.map_or(0, |v| v.len()) > self.max_value_size {
+            return Err(Error::ValueTooLarge);
+        }
+        Ok(())
+    }
+}
+
+/// How page files are verified when a store is opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyFilesMode {
+    /// Verifies every file before open returns, and fails with the complete
+    /// list of corrupted files. Slow with many files.
+    Eager,
+    /// Verifies a file on its first access, only the reads touching a
+    /// corrupted file fail. Each file is verified once.
+    Lazy,
+    /// Opens immediately and verifies all files in a one-shot background job,
+    /// reads never wait for it.
+    Background,
+}
+
 /// Options that control manual flush operations.
 #[derive(Clone, Debug)]
 pub struct FlushOptions {
@@ -173,4 +364,46 @@ impl Default for FlushOptions {
 //
 //     jobs: Vec<E::JoinHandle<()>>,
 //     shutdown: ShutdownNotifier,
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_io_buffer_size() {
+        let mut options = Options::default();
+        assert!(options.validate().is_ok());
+        options.io_buffer_size = 4096;
+        assert!(options.validate().is_ok());
+        options.io_buffer_size = 0;
+        assert!(options.validate().is_err());
+        options.io_buffer_size = 4095;
+        assert!(options.validate().is_err());
+    }
+
+    #[test]
+    fn validate_record_size() {
+        let mut options = Options {
+            max_key_size: 4,
+            max_value_size: 8,
+            ..Default::default()
+        };
+        assert!(options.check_record_size(&[0; 4], Some(&[0; 8])).is_ok());
+        assert!(options.check_record_size(&[], Some(&[])).is_ok());
+        assert!(options.check_record_size(&[0; 4], None).is_ok());
+        assert!(matches!(
+            options.check_record_size(&[0; 5], Some(&[])),
+            Err(Error::KeyTooLarge)
+        ));
+        assert!(matches!(
+            options.check_record_size(&[0; 4], Some(&[0; 9])),
+            Err(Error::ValueTooLarge)
+        ));
+
+        options.max_value_size = u32::MAX as usize;
+        assert!(options.validate().is_err());
+        options.max_value_size = MAX_RECORD_SIZE - options.max_key_size;
+        assert!(options.validate().is_ok());
+    }
+}
@@ -1 +1,6 @@
-mod file_reader;
\ No newline at end of file
+mod file_reader;
+mod quarantine;
+mod score;
+mod scrub;
+mod verify;
+mod version;
@@ -0,0 +1,229 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use tokio::fs::{create_dir_all, metadata, read_dir, remove_file, rename, write};
+
+use crate::file::open_files::OpenFileLimiter;
+use crate::store::page_store::verify::{is_corruption, verify_file};
+use crate::utils::trace::log_info;
+
+const QUARANTINE_DIR: &str = "quarantine";
+const SIDECAR_SUFFIX: &str = "json";
+
+/// What [`Quarantine::handle_orphan`] did to an orphaned page file.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum OrphanAction {
+    /// The file is partially written, it is deleted.
+    Deleted,
+    /// The file is complete, it is moved to the given path.
+    Quarantined(PathBuf),
+}
+
+/// Keeps the page files that are not referenced by the manifest.
+///
+/// An unreferenced file is usually the output of a crashed flush, but it may
+/// also be the only copy of recent data if the manifest lost some edits. So a
+/// complete file is moved into `quarantine/` with a sidecar describing why,
+/// instead of being deleted. Only files without a valid footer are deleted
+/// directly. When the quarantined files exceed `max_size` bytes, the oldest
+/// ones are deleted.
+pub(crate) struct Quarantine {
+    dir: PathBuf,
+    max_size: u64,
+    open_files: Arc<OpenFileLimiter>,
+}
+
+impl Quarantine {
+    pub(crate) fn new(base: impl AsRef<Path>, max_size: u64) -> Self {
+        Self {
+            dir: base.as_ref().join(QUARANTINE_DIR),
+            max_size,
+            open_files: Arc::default(),
+        }
+    }
+
+    /// Counts the files opened to check orphans against `limiter`.
+    pub(crate) fn with_open_file_limiter(mut self, limiter: Arc<OpenFileLimiter>) -> Self {
+        self.open_files = limiter;
+        self
+    }
+
+    /// Quarantines or deletes the orphaned page file at `path`.
+    pub(crate) async fn handle_orphan(&self, path: &Path, reason: &str) -> Result<OrphanAction> {
+        if !is_complete(path, &self.open_files).await? {
+            log_info!(path = ?path, reason, "delete partial orphaned file");
+            remove_file(path).await?;
+            return Ok(OrphanAction::Deleted);
+        }
+
+        create_dir_all(&self.dir).await?;
+        let files = self.files().await?;
+        let seq = files.last().map_or(0, |(seq, _)| seq + 1);
+        let name = path.file_name().unwrap().to_string_lossy();
+        let target = self.dir.join(format!("{seq}.{name}"));
+        let size = metadata(path).await?.len();
+        let quarantined_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let sidecar = format!(
+            "{{\"file\":\"{}\",\"reason\":\"{}\",\"quarantined_at\":{},\"size\":{}}}\n",
+            escape(&name),
+            escape(reason),
+            quarantined_at,
+            size
+        );
+        write(sidecar_path(&target), sidecar).await?;
+        rename(path, &target).await?;
+        log_info!(path = ?path, target = ?target, reason, "quarantine orphaned file");
+        self.prune().await?;
+        Ok(OrphanAction::Quarantined(target))
+    }
+
+    /// Returns the quarantined files, oldest first.
+    pub(crate) async fn files(&self) -> Result<Vec<(u64, PathBuf)>> {
+        let mut files = Vec::new();
+        let mut dir = match read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == SIDECAR_SUFFIX) {
+                continue;
+            }
+            let name = entry.file_name();
+            let seq = name
+                .to_str()
+                .and_then(|name| name.split_once('.'))
+                .and_then(|(seq, _)| seq.parse::<u64>().ok());
+            if let Some(seq) = seq {
+                files.push((seq, path));
+            }
+        }
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    /// Deletes the oldest files until the total size is within the bound, the
+    /// newest file is always kept.
+    async fn prune(&self) -> Result<()> {
+        let files = self.files().await?;
+        let mut sizes = Vec::with_capacity(files.len());
+        for (_, path) in &files {
+            sizes.push(metadata(path).await?.len());
+        }
+        let mut total: u64 = sizes.iter().sum();
+        for ((_, path), size) in files.iter().zip(sizes).take(files.len().saturating_sub(1)) {
+            if total <= self.max_size {
+                break;
+            }
+            log_info!(path = ?path, "delete the oldest quarantined file");
+            remove_file(path).await?;
+            remove_file(sidecar_path(path)).await?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if the file has a valid footer and meta block, or an error
+/// if the file can't be read.
+async fn is_complete(path: &Path, open_files: &OpenFileLimiter) -> Result<bool> {
+    match verify_file(path, open_files).await {
+        Ok(()) => Ok(true),
+        Err(err) if is_corruption(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIDECAR_SUFFIX);
+    path.into()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::file_reader::tests::write_test_file;
+    use crate::file::page_iter::FilePageIter;
+    use crate::file::types::FOOTER_LEN;
+
+    #[tokio::test]
+    async fn quarantine_complete_file() {
+        let base = tempdir::TempDir::new("quarantine").unwrap();
+        let path = base.path().join("1");
+        let pages = write_test_file(&path).await;
+        let open_files = Arc::new(OpenFileLimiter::new(1));
+        let quarantine =
+            Quarantine::new(base.path(), u64::MAX).with_open_file_limiter(open_files.clone());
+
+        let action = quarantine.handle_orphan(&path, "not in \"manifest\"").await.unwrap();
+        let target = base.path().join(QUARANTINE_DIR).join("0.1");
+        assert_eq!(action, OrphanAction::Quarantined(target.clone()));
+        assert!(!path.exists());
+        assert_eq!(open_files.available(), Some(1));
+        let sidecar = std::fs::read_to_string(sidecar_path(&target)).unwrap();
+        assert!(sidecar.starts_with(r#"{"file":"1","reason":"not in \"manifest\"","#));
+
+        // The quarantined pages are still readable.
+        let addrs: Vec<_> = FilePageIter::open(&target).unwrap().map(|p| p.unwrap().0).collect();
+        assert_eq!(addrs, pages.iter().map(|(addr, _)| *addr).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn delete_partial_file() {
+        let base = tempdir::TempDir::new("quarantine").unwrap();
+        let path = base.path().join("1");
+        write_test_file(&path).await;
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() - 1]).unwrap();
+        let quarantine = Quarantine::new(base.path(), u64::MAX);
+
+        let action = quarantine.handle_orphan(&path, "orphaned").await.unwrap();
+        assert_eq!(action, OrphanAction::Deleted);
+        assert!(!path.exists());
+        assert!(quarantine.files().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn delete_file_with_corrupted_footer() {
+        let base = tempdir::TempDir::new("quarantine").unwrap();
+        let path = base.path().join("1");
+        write_test_file(&path).await;
+        let mut content = std::fs::read(&path).unwrap();
+        let checksum_pos = content.len() - FOOTER_LEN + 16;
+        content[checksum_pos] = 0xff;
+        std::fs::write(&path, content).unwrap();
+        let quarantine = Quarantine::new(base.path(), u64::MAX);
+
+        let action = quarantine.handle_orphan(&path, "orphaned").await.unwrap();
+        assert_eq!(action, OrphanAction::Deleted);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn prune_oldest_files() {
+        let base = tempdir::TempDir::new("quarantine").unwrap();
+        write_test_file(&base.path().join("0")).await;
+        let file_size = std::fs::metadata(base.path().join("0")).unwrap().len();
+        let quarantine = Quarantine::new(base.path(), file_size * 2);
+        for i in 0..4 {
+            let path = base.path().join(i.to_string());
+            if i > 0 {
+                write_test_file(&path).await;
+            }
+            quarantine.handle_orphan(&path, "orphaned").await.unwrap();
+        }
+        let files = quarantine.files().await.unwrap();
+        let seqs: Vec<_> = files.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, vec![2, 3]);
+        assert!(!sidecar_path(&base.path().join(QUARANTINE_DIR).join("0.0")).exists());
+    }
+}
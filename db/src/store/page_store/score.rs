@@ -0,0 +1,138 @@
+/// The weights of the components of a compaction score, they sum to 1.
+const TOMBSTONE_WEIGHT: f64 = 0.5;
+const OVERLAP_WEIGHT: f64 = 0.3;
+const AGE_WEIGHT: f64 = 0.2;
+
+/// The statistics of a page file used to prioritize compaction.
+#[derive(Clone, Debug)]
+pub(crate) struct FileStats {
+    pub(crate) file_id: u32,
+    pub(crate) num_entries: u64,
+    pub(crate) num_tombstones: u64,
+    /// The smallest and the largest raw keys in the file.
+    pub(crate) smallest_key: Vec<u8>,
+    pub(crate) largest_key: Vec<u8>,
+    /// The wall-clock time the file was created, in microseconds since the
+    /// unix epoch.
+    pub(crate) created_at: u64,
+}
+
+impl FileStats {
+    fn overlaps(&self, other: &FileStats) -> bool {
+        self.smallest_key <= other.largest_key && other.smallest_key <= self.largest_key
+    }
+}
+
+/// The compaction score of a file and its components, each in `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FileScore {
+    pub(crate) file_id: u32,
+    pub(crate) score: f64,
+    /// The fraction of entries that are tombstones.
+    pub(crate) tombstone_density: f64,
+    /// The fraction of other files whose key ranges overlap this file.
+    pub(crate) overlap: f64,
+    /// The age relative to the oldest file.
+    pub(crate) age: f64,
+}
+
+/// Scores `files` for compaction, returns them from the highest score to the
+/// lowest.
+///
+/// Compacting a file full of tombstones drops them, and compacting a file
+/// that overlaps many others reduces the files a read has to look at, so
+/// both reduce read amplification faster than picking by size. Age breaks
+/// ties in favor of files that have not been rewritten for a long time,
+/// which keeps the same recent data from being rewritten repeatedly.
+pub(crate) fn score_files(files: &[FileStats], now: u64) -> Vec<FileScore> {
+    let max_age = files
+        .iter()
+        .map(|file| now.saturating_sub(file.created_at))
+        .max()
+        .unwrap_or(0);
+    let mut scores: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let tombstone_density = if file.num_entries == 0 {
+                0.0
+            } else {
+                file.num_tombstones as f64 / file.num_entries as f64
+            };
+            let overlap = if files.len() <= 1 {
+                0.0
+            } else {
+                let overlapped = files
+                    .iter()
+                    .filter(|other| other.file_id != file.file_id && file.overlaps(other))
+                    .count();
+                overlapped as f64 / (files.len() - 1) as f64
+            };
+            let age = if max_age == 0 {
+                0.0
+            } else {
+                now.saturating_sub(file.created_at) as f64 / max_age as f64
+            };
+            FileScore {
+                file_id: file.file_id,
+                score: TOMBSTONE_WEIGHT * tombstone_density
+                    + OVERLAP_WEIGHT * overlap
+                    + AGE_WEIGHT * age,
+                tombstone_density,
+                overlap,
+                age,
+            }
+        })
+        .collect();
+    scores.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.file_id.cmp(&b.file_id)));
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(file_id: u32, entries: u64, tombstones: u64, keys: (&[u8], &[u8]), created_at: u64) -> FileStats {
+        FileStats {
+            file_id,
+            num_entries: entries,
+            num_tombstones: tombstones,
+            smallest_key: keys.0.to_vec(),
+            largest_key: keys.1.to_vec(),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn rank_tombstone_heavy_overlapping_file_first() {
+        let files = [
+            // A large clean file that doesn't overlap others, and is the oldest.
+            file(1, 1_000_000, 0, (b"x", b"z"), 0),
+            // Small, tombstone heavy and overlapping the others.
+            file(2, 1000, 600, (b"a", b"m"), 50),
+            file(3, 1000, 0, (b"b", b"c"), 80),
+            file(4, 1000, 0, (b"k", b"p"), 90),
+        ];
+        let scores = score_files(&files, 100);
+        let ranking: Vec<_> = scores.iter().map(|s| s.file_id).collect();
+        assert_eq!(ranking[0], 2);
+        let heavy = scores[0];
+        assert_eq!(heavy.tombstone_density, 0.6);
+        assert_eq!(heavy.overlap, 2.0 / 3.0);
+        assert_eq!(heavy.age, 0.5);
+        let clean = scores.iter().find(|s| s.file_id == 1).unwrap();
+        assert_eq!(clean.overlap, 0.0);
+        assert_eq!(clean.age, 1.0);
+        assert!(heavy.score > clean.score);
+    }
+
+    #[test]
+    fn score_edge_cases() {
+        assert!(score_files(&[], 0).is_empty());
+        let scores = score_files(&[file(1, 0, 0, (b"a", b"b"), 10)], 10);
+        assert_eq!(scores[0].score, 0.0);
+        // Equal scores are ordered by file id.
+        let files = [file(2, 1, 0, (b"a", b"a"), 0), file(1, 1, 0, (b"b", b"b"), 0)];
+        let ranking: Vec<_> = score_files(&files, 0).iter().map(|s| s.file_id).collect();
+        assert_eq!(ranking, vec![1, 2]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use tokio::fs::{read_to_string, remove_file, rename, write};
+use tokio::runtime::Handle;
+use tokio::task::spawn_blocking;
+
+use crate::file::open_files::OpenFileLimiter;
+use crate::file::page_iter::FilePageIter;
+use crate::page::iter::SeekableIterator;
+use crate::utils::rate_limiter::{IoPriority, RateLimiter};
+use crate::utils::trace::{log_info, log_warn};
+
+const CURSOR_FILE_NAME: &str = "SCRUB_CURSOR";
+const TEMP_SUFFIX: &str = "tmpdb";
+
+/// A corruption found by the scrubber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Corruption {
+    pub(crate) file_id: u32,
+    /// The address of the corrupted page, or `None` if the file can't be
+    /// opened, e.g. its footer or meta block is corrupted.
+    pub(crate) page_addr: Option<u64>,
+}
+
+/// The position of the next page to verify.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct ScrubCursor {
+    file_id: u32,
+    page_addr: u64,
+}
+
+/// The result of a scrub cycle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ScrubProgress {
+    pub(crate) pages: usize,
+    pub(crate) bytes: u64,
+    pub(crate) corruptions: usize,
+    /// True if the pass over all files is finished, the next cycle starts
+    /// from the first file again.
+    pub(crate) finished: bool,
+}
+
+/// Verifies the pages of the page files in the background, so that silent
+/// disk corruption is found before a read trips over it.
+///
+/// Pages are verified in the order of file id and page address, a bounded
+/// number of pages per cycle. Reading a page verifies its checksum and
+/// decompresses it. The position is persisted in `SCRUB_CURSOR` after each
+/// cycle, so a pass resumes where it left off across restarts. Reads are
+/// charged to the background rate limiter at low priority.
+///
+/// A file that disappears during the pass is skipped, it is removed by space
+/// reclamation after the caller listed it.
+pub(crate) struct Scrubber {
+    base: PathBuf,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    open_files: Arc<OpenFileLimiter>,
+}
+
+impl Scrubber {
+    pub(crate) fn new(base: impl Into<PathBuf>) -> Self {
+        Self {
+            base: base.into(),
+            rate_limiter: None,
+            open_files: Arc::default(),
+        }
+    }
+
+    /// Limits the read rate of the scrubber with `limiter`.
+    pub(crate) fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Counts the files opened by the scrubber against `limiter`.
+    pub(crate) fn with_open_file_limiter(mut self, limiter: Arc<OpenFileLimiter>) -> Self {
+        self.open_files = limiter;
+        self
+    }
+
+    /// Verifies up to `max_pages` pages of the live `files` (file id, path),
+    /// starting from the persisted cursor, and calls `on_corruption` for each
+    /// corruption found.
+    ///
+    /// The pages are read with blocking positioned reads, so the cycle runs on
+    /// the blocking thread pool instead of the async workers.
+    pub(crate) async fn scrub(
+        &self,
+        files: impl IntoIterator<Item = (u32, PathBuf)>,
+        max_pages: usize,
+        mut on_corruption: impl FnMut(Corruption),
+    ) -> Result<ScrubProgress> {
+        let mut files: Vec<_> = files.into_iter().collect();
+        files.sort_unstable_by_key(|(file_id, _)| *file_id);
+        let cursor = self.load_cursor().await?.unwrap_or_default();
+        let rate_limiter = self.rate_limiter.clone();
+        let open_files = self.open_files.clone();
+        let runtime = Handle::current();
+        let cycle = spawn_blocking(move || {
+            let rate_limiter = rate_limiter.as_deref();
+            scrub_cycle(&runtime, rate_limiter, &open_files, files, cursor, max_pages)
+        })
+        .await?;
+
+        for corruption in cycle.corruptions {
+            on_corruption(corruption);
+        }
+        let mut progress = cycle.progress;
+        match cycle.next {
+            Some(cursor) => self.save_cursor(cursor).await?,
+            None => {
+                self.reset_cursor().await?;
+                progress.finished = true;
+                log_info!(pages = progress.pages, "scrub: finish a pass");
+            }
+        }
+        Ok(progress)
+    }
+
+    async fn load_cursor(&self) -> Result<Option<ScrubCursor>> {
+        let content = match read_to_string(self.cursor_path()).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).context("read scrub cursor"),
+        };
+        // A malformed cursor restarts the pass, it is only a hint.
+        let cursor = content.trim().split_once(' ').and_then(|(file_id, page_addr)| {
+            Some(ScrubCursor {
+                file_id: file_id.parse().ok()?,
+                page_addr: page_addr.parse().ok()?,
+            })
+        });
+        Ok(cursor)
+    }
+
+    async fn save_cursor(&self, cursor: ScrubCursor) -> Result<()> {
+        let path = self.cursor_path();
+        let mut tmp_path = path.clone();
+        tmp_path.set_extension(TEMP_SUFFIX);
+        write(&tmp_path, format!("{} {}\n", cursor.file_id, cursor.page_addr))
+            .await
+            .context("write scrub cursor")?;
+        rename(&tmp_path, &path).await.context("rename scrub cursor")?;
+        Ok(())
+    }
+
+    async fn reset_cursor(&self) -> Result<()> {
+        match remove_file(self.cursor_path()).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => {
+                Err(err).context("remove scrub cursor")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn cursor_path(&self) -> PathBuf {
+        self.base.join(CURSOR_FILE_NAME)
+    }
+}
+
+/// The result of the reads of a scrub cycle.
+struct ScrubCycle {
+    progress: ScrubProgress,
+    corruptions: Vec<Corruption>,
+    /// The position to resume from, or `None` if the pass is finished.
+    next: Option<ScrubCursor>,
+}
+
+/// Verifies up to `max_pages` pages of `files` from `cursor`, blocks the
+/// calling thread.
+fn scrub_cycle(
+    runtime: &Handle,
+    rate_limiter: Option<&RateLimiter>,
+    open_files: &OpenFileLimiter,
+    files: Vec<(u32, PathBuf)>,
+    cursor: ScrubCursor,
+    max_pages: usize,
+) -> ScrubCycle {
+    let mut cycle = ScrubCycle {
+        progress: ScrubProgress::default(),
+        corruptions: Vec::new(),
+        next: None,
+    };
+    for (file_id, path) in files.into_iter().filter(|(id, _)| *id >= cursor.file_id) {
+        let permit = runtime.block_on(open_files.acquire());
+        let mut iter = match FilePageIter::open(&path) {
+            Ok(iter) => iter.with_open_file_permit(permit),
+            Err(_) if !path.exists() => continue,
+            Err(_) => {
+                log_warn!(file_id, "scrub: corrupted file");
+                cycle.corruptions.push(Corruption {
+                    file_id,
+                    page_addr: None,
+                });
+                cycle.progress.corruptions += 1;
+                continue;
+            }
+        };
+        if file_id == cursor.file_id {
+            iter.seek(&cursor.page_addr);
+        }
+        while let Some((page_addr, length)) = iter.peek() {
+            if cycle.progress.pages >= max_pages {
+                cycle.next = Some(ScrubCursor { file_id, page_addr });
+                return cycle;
+            }
+            if let Some(limiter) = rate_limiter {
+                runtime.block_on(limiter.request(IoPriority::Low, length));
+            }
+            if iter.next().unwrap().is_err() {
+                log_warn!(file_id, page_addr, "scrub: corrupted page");
+                cycle.corruptions.push(Corruption {
+                    file_id,
+                    page_addr: Some(page_addr),
+                });
+                cycle.progress.corruptions += 1;
+            }
+            cycle.progress.pages += 1;
+            cycle.progress.bytes += length;
+        }
+    }
+    cycle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::file_reader::tests::write_test_file;
+    use crate::file::types::FOOTER_LEN;
+
+    #[tokio::test]
+    async fn scrub_resumes_across_restarts() {
+        let base = tempdir::TempDir::new("scrub").unwrap();
+        let mut files = Vec::new();
+        let mut pages = Vec::new();
+        for file_id in [1, 2] {
+            let path = base.path().join(file_id.to_string());
+            pages.push(write_test_file(&path).await);
+            files.push((file_id, path));
+        }
+        // Corrupts the first page of file 2.
+        let mut content = std::fs::read(&files[1].1).unwrap();
+        content[20] ^= 0xff;
+        std::fs::write(&files[1].1, content).unwrap();
+
+        let mut corruptions = Vec::new();
+        let mut total = ScrubProgress::default();
+        let mut cycles = 0;
+        while !total.finished {
+            // A new scrubber for each cycle, as if the store is restarted.
+            let scrubber = Scrubber::new(base.path());
+            let progress = scrubber
+                .scrub(files.clone(), 2, |c| corruptions.push(c))
+                .await
+                .unwrap();
+            total.pages += progress.pages;
+            total.corruptions += progress.corruptions;
+            total.finished = progress.finished;
+            cycles += 1;
+        }
+        assert_eq!(cycles, 3);
+        assert_eq!(total.pages, 6);
+        assert_eq!(total.corruptions, 1);
+        assert_eq!(
+            corruptions,
+            vec![Corruption {
+                file_id: 2,
+                page_addr: Some(pages[1][0].0),
+            }]
+        );
+        assert!(!base.path().join(CURSOR_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn scrub_missing_and_broken_files() {
+        let base = tempdir::TempDir::new("scrub").unwrap();
+        let mut files = Vec::new();
+        for file_id in [1, 2, 3, 4] {
+            let path = base.path().join(file_id.to_string());
+            write_test_file(&path).await;
+            files.push((file_id, path));
+        }
+        let limiter = Arc::new(RateLimiter::new(1 << 30));
+        let open_files = Arc::new(OpenFileLimiter::new(1));
+        let scrubber = Scrubber::new(base.path())
+            .with_rate_limiter(limiter.clone())
+            .with_open_file_limiter(open_files.clone());
+        let progress = scrubber.scrub(files.clone(), 3, |_| {}).await.unwrap();
+        assert_eq!(progress.pages, 3);
+        assert_eq!(limiter.stats().total_bytes, progress.bytes);
+        assert_eq!(open_files.available(), Some(1));
+
+        // File 2 is reclaimed, the footer of file 3 is truncated and the
+        // checksum type of file 4 is garbage before the next cycle.
+        std::fs::remove_file(&files[1].1).unwrap();
+        let content = std::fs::read(&files[2].1).unwrap();
+        std::fs::write(&files[2].1, &content[..content.len() - 1]).unwrap();
+        let mut content = std::fs::read(&files[3].1).unwrap();
+        let checksum_pos = content.len() - FOOTER_LEN + 16;
+        content[checksum_pos] = 2;
+        std::fs::write(&files[3].1, content).unwrap();
+        let mut corruptions = Vec::new();
+        let progress = scrubber
+            .scrub(files.clone(), 3, |c| corruptions.push(c))
+            .await
+            .unwrap();
+        assert!(progress.finished);
+        assert_eq!(progress.pages, 0);
+        assert_eq!(
+            corruptions,
+            vec![
+                Corruption {
+                    file_id: 3,
+                    page_addr: None,
+                },
+                Corruption {
+                    file_id: 4,
+                    page_addr: None,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use rustc_hash::FxHashMap;
+use tokio::sync::OnceCell;
+
+use crate::error::Error;
+use crate::file::file_reader::FileReader;
+use crate::file::open_files::OpenFileLimiter;
+use crate::store::VerifyFilesMode;
+use crate::utils::trace::{log_info, log_warn};
+
+/// The buffer size to read a footer and a meta block, they are small.
+const VERIFY_BUFFER_SIZE: usize = 64 << 10;
+
+/// A page file that failed verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FileProblem {
+    pub(crate) file_id: u32,
+    pub(crate) error: String,
+}
+
+/// The result of verifying page files when a store is opened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct VerifyReport {
+    pub(crate) mode: VerifyFilesMode,
+    /// The number of files verified before open returns, zero unless eager.
+    pub(crate) files: usize,
+    pub(crate) duration: Duration,
+    pub(crate) problems: Vec<FileProblem>,
+}
+
+/// Verifies the footer, the meta block and the file meta of the page file at
+/// `path`, the file counts against `open_files` while it is verified.
+pub(crate) async fn verify_file(path: &Path, open_files: &OpenFileLimiter) -> Result<()> {
+    let mut reader =
+        FileReader::open_limited(open_files, path, false, 4096, VERIFY_BUFFER_SIZE).await?;
+    reader.read_file_meta().await?;
+    Ok(())
+}
+
+/// Verifies all `files` (file id, path), returns the problems found.
+pub(crate) async fn verify_files(
+    files: impl IntoIterator<Item = (u32, PathBuf)>,
+    open_files: &OpenFileLimiter,
+) -> Vec<FileProblem> {
+    let mut problems = Vec::new();
+    for (file_id, path) in files {
+        if let Err(err) = verify_file(&path, open_files).await {
+            log_warn!(file_id, "verify: corrupted file");
+            problems.push(FileProblem {
+                file_id,
+                error: format!("{err:#}"),
+            });
+        }
+    }
+    problems
+}
+
+/// Verifies the referenced `files` (file id, path) as the open of a store
+/// requires by `mode`.
+///
+/// Only [`VerifyFilesMode::Eager`] verifies here, and fails with all the
+/// corrupted files if any. The other modes return immediately, the caller
+/// verifies files through a [`LazyVerifier`] or a background job running
+/// [`verify_files`].
+pub(crate) async fn verify_on_open(
+    mode: VerifyFilesMode,
+    files: impl IntoIterator<Item = (u32, PathBuf)>,
+    open_files: &OpenFileLimiter,
+) -> Result<VerifyReport> {
+    let start = Instant::now();
+    let mut report = VerifyReport {
+        mode,
+        files: 0,
+        duration: Duration::ZERO,
+        problems: Vec::new(),
+    };
+    if mode == VerifyFilesMode::Eager {
+        let files: Vec<_> = files.into_iter().collect();
+        report.files = files.len();
+        report.problems = verify_files(files, open_files).await;
+        report.duration = start.elapsed();
+    }
+    log_info!(
+        mode = ?report.mode,
+        files = report.files,
+        duration = ?report.duration,
+        "verify files on open"
+    );
+    if !report.problems.is_empty() {
+        let problems: Vec<_> = report
+            .problems
+            .iter()
+            .map(|p| format!("file {}: {}", p.file_id, p.error))
+            .collect();
+        return Err(anyhow!(Error::Corrupted).context(format!(
+            "{} corrupted files: {}",
+            problems.len(),
+            problems.join("; ")
+        )));
+    }
+    Ok(report)
+}
+
+/// Verifies each page file on its first access, and caches the result, so a
+/// file is verified once however many reads touch it.
+///
+/// Only corruption is cached. A file that can't be read for other reasons,
+/// e.g. too many open files or a rename in progress, is verified again on the
+/// next access.
+#[derive(Default)]
+pub(crate) struct LazyVerifier {
+    /// file id -> the error message if the file is corrupted
+    results: Mutex<FxHashMap<u32, Arc<OnceCell<Option<String>>>>>,
+    open_files: Arc<OpenFileLimiter>,
+}
+
+impl LazyVerifier {
+    /// Counts the files opened to verify against `limiter`.
+    pub(crate) fn with_open_file_limiter(mut self, limiter: Arc<OpenFileLimiter>) -> Self {
+        self.open_files = limiter;
+        self
+    }
+
+    /// Returns an error if the file is corrupted or can't be verified,
+    /// verifies it first if it has not been verified yet.
+    pub(crate) async fn check(&self, file_id: u32, path: &Path) -> Result<()> {
+        let cell = self
+            .results
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .entry(file_id)
+            .or_default()
+            .clone();
+        let result = cell
+            .get_or_try_init(|| async {
+                match verify_file(path, &self.open_files).await {
+                    Ok(()) => Ok(None),
+                    Err(err) if is_corruption(&err) => {
+                        log_warn!(file_id, "verify: corrupted file");
+                        Ok(Some(format!("{err:#}")))
+                    }
+                    Err(err) => Err(err),
+                }
+            })
+            .await?;
+        match result {
+            None => Ok(()),
+            Some(err) => Err(anyhow!(Error::Corrupted).context(format!("file {file_id}: {err}"))),
+        }
+    }
+
+    /// Forgets the result of a deleted file.
+    pub(crate) fn remove(&self, file_id: u32) {
+        self.results
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(&file_id);
+    }
+}
+
+/// Returns true if `err` is caused by the content of the file, rather than
+/// by a failure to read it.
+pub(super) fn is_corruption(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        err.is::<Error>()
+            || err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == ErrorKind::UnexpectedEof)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::file_reader::tests::write_test_file;
+    use crate::file::types::FOOTER_LEN;
+
+    /// Writes a good file 1, a file 2 with a truncated footer and a file 3
+    /// with an unsupported checksum type in the footer.
+    async fn write_files(dir: &Path) -> Vec<(u32, PathBuf)> {
+        let files = vec![(1, dir.join("1")), (2, dir.join("2")), (3, dir.join("3"))];
+        for (_, path) in &files {
+            write_test_file(path).await;
+        }
+        let len = std::fs::metadata(&files[1].1).unwrap().len();
+        let file = std::fs::OpenOptions::new().write(true).open(&files[1].1).unwrap();
+        file.set_len(len - 4).unwrap();
+        let mut content = std::fs::read(&files[2].1).unwrap();
+        let checksum_pos = content.len() - FOOTER_LEN + 16;
+        content[checksum_pos] = 2;
+        std::fs::write(&files[2].1, content).unwrap();
+        files
+    }
+
+    #[tokio::test]
+    async fn verify_eager() {
+        let dir = tempdir::TempDir::new("verify").unwrap();
+        let files = write_files(dir.path()).await;
+        let open_files = OpenFileLimiter::new(1);
+
+        let report = verify_on_open(VerifyFilesMode::Eager, files[..1].to_vec(), &open_files)
+            .await
+            .unwrap();
+        assert_eq!(report.files, 1);
+        assert!(report.problems.is_empty());
+
+        let err = verify_on_open(VerifyFilesMode::Eager, files.clone(), &open_files)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Corrupted)));
+        assert!(err.to_string().starts_with("2 corrupted files: file 2:"), "{err}");
+        assert!(err.to_string().contains("; file 3:"), "{err}");
+
+        // The other modes don't verify on open.
+        let report = verify_on_open(VerifyFilesMode::Background, files.clone(), &open_files)
+            .await
+            .unwrap();
+        assert_eq!(report.files, 0);
+        let problems = verify_files(files, &open_files).await;
+        let ids: Vec<_> = problems.iter().map(|p| p.file_id).collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn verify_lazy() {
+        let dir = tempdir::TempDir::new("verify").unwrap();
+        let files = write_files(dir.path()).await;
+        let open_files = Arc::new(OpenFileLimiter::new(1));
+        let verifier = LazyVerifier::default().with_open_file_limiter(open_files.clone());
+        verifier.check(1, &files[0].1).await.unwrap();
+        assert_eq!(open_files.available(), Some(1));
+        for (file_id, path) in &files[1..] {
+            let err = verifier.check(*file_id, path).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<Error>(), Some(Error::Corrupted)));
+        }
+
+        // The results are cached, the files are not verified again.
+        write_test_file(&files[1].1).await;
+        std::fs::remove_file(&files[0].1).unwrap();
+        verifier.check(1, &files[0].1).await.unwrap();
+        assert!(verifier.check(2, &files[1].1).await.is_err());
+        verifier.remove(2);
+        verifier.check(2, &files[1].1).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn verify_lazy_io_error_not_cached() {
+        let dir = tempdir::TempDir::new("verify").unwrap();
+        let path = dir.path().join("1");
+        let verifier = LazyVerifier::default();
+        // The file is being renamed into place.
+        let err = verifier.check(1, &path).await.unwrap_err();
+        assert!(err.downcast_ref::<Error>().is_none());
+        write_test_file(&path).await;
+        verifier.check(1, &path).await.unwrap();
+    }
+}
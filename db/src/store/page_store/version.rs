@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use crate::store::manifest::{Manifest, ReplayStats};
+use crate::store::meta::{NewFile, StreamEdit, VersionEdit};
+use crate::utils::trace::{log_debug, log_info};
+
+/// The files recorded by the version edits, and the references between them.
+///
+/// A file deleted by a version edit stays on disk until no other file
+/// references its page groups, because pages compacted into a new file may
+/// still point into it.
+#[derive(Default)]
+pub(crate) struct FileSet {
+    /// Files visible in the current version.
+    live: BTreeMap<u32, NewFile>,
+    /// Files deleted from the version but not yet removed from disk.
+    obsolete: BTreeMap<u32, NewFile>,
+}
+
+impl FileSet {
+    /// Builds the file set by replaying `edits` in order.
+    pub(crate) fn from_edits<'a>(edits: impl IntoIterator<Item = &'a VersionEdit>) -> Self {
+        let mut files = Self::default();
+        for edit in edits {
+            files.apply(edit);
+        }
+        files
+    }
+
+    /// Builds the file set from the manifest, applying one edit at a time so
+    /// that memory usage only depends on the live state.
+    pub(crate) async fn recover(manifest: &Manifest) -> anyhow::Result<(Self, ReplayStats)> {
+        let mut files = Self::default();
+        let stats = manifest
+            .replay(|edit| {
+                files.apply(&edit);
+                Ok(())
+            })
+            .await?;
+        log_info!(
+            edits = stats.edits,
+            bytes = stats.bytes,
+            live_files = files.live.len(),
+            obsolete_files = files.obsolete.len(),
+            "recover file set"
+        );
+        Ok((files, stats))
+    }
+
+    pub(crate) fn apply(&mut self, edit: &VersionEdit) {
+        let Some(stream) = edit.file_stream.as_ref() else {
+            return;
+        };
+        for file in &stream.new_files {
+            self.live.insert(file.id, file.clone());
+        }
+        for file_id in &stream.deleted_files {
+            if let Some(file) = self.live.remove(file_id) {
+                self.obsolete.insert(*file_id, file);
+            }
+        }
+    }
+
+    /// Returns an edit that rebuilds this file set when applied to an empty
+    /// one, for the manifest to roll to a snapshot.
+    ///
+    /// Obsolete files are recorded as added and then deleted, so that they
+    /// and their references survive the roll until they are cleaned up.
+    pub(crate) fn snapshot_edit(&self) -> VersionEdit {
+        VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files: self.live.values().chain(self.obsolete.values()).cloned().collect(),
+                deleted_files: self.obsolete.keys().copied().collect(),
+            }),
+        }
+    }
+
+    /// Returns the files visible in the current version, ordered by id.
+    pub(crate) fn live_files(&self) -> impl Iterator<Item = &NewFile> {
+        self.live.values()
+    }
+
+    /// Returns whether the file is visible in the current version.
+    pub(crate) fn is_live(&self, file_id: u32) -> bool {
+        self.live.contains_key(&file_id)
+    }
+
+    /// Returns the largest LSN recorded by the files on disk, to seed the LSN
+    /// counter on open.
+    pub(crate) fn max_lsn(&self) -> u64 {
+        self.live
+            .values()
+            .chain(self.obsolete.values())
+            .filter_map(|file| file.max_lsn)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of files on disk that reference `file_id`.
+    pub(crate) fn num_referrers(&self, file_id: u32) -> usize {
+        self.live
+            .values()
+            .chain(self.obsolete.values())
+            .filter(|file| file.id != file_id && file.referenced_files.contains(&file_id))
+            .count()
+    }
+
+    /// Removes the obsolete files that are no longer referenced, returns their
+    /// ids so that they can be deleted from disk.
+    ///
+    /// Removing a file releases its own references, so files that are only
+    /// referenced by removed files are removed too.
+    pub(crate) fn cleanup(&mut self) -> Vec<u32> {
+        let mut removed = Vec::new();
+        loop {
+            let unreferenced: Vec<u32> = self
+                .obsolete
+                .keys()
+                .copied()
+                .filter(|&id| self.num_referrers(id) == 0)
+                .collect();
+            if unreferenced.is_empty() {
+                break;
+            }
+            for id in unreferenced {
+                self.obsolete.remove(&id);
+                removed.push(id);
+            }
+        }
+        removed.sort_unstable();
+        log_debug!(files = ?removed, "clean up obsolete files");
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_file(id: u32, referenced_files: Vec<u32>) -> NewFile {
+        NewFile {
+            referenced_files,
+            ..id.into()
+        }
+    }
+
+    fn edit(new_files: Vec<NewFile>, deleted_files: Vec<u32>) -> VersionEdit {
+        VersionEdit {
+            file_stream: Some(StreamEdit {
+                new_files,
+                deleted_files,
+            }),
+        }
+    }
+
+    #[test]
+    fn referenced_file_survives_cleanup() {
+        let edits = vec![
+            edit(vec![new_file(1, vec![]), new_file(2, vec![])], vec![]),
+            // File 3 is compacted from file 1, but still references file 2.
+            edit(vec![new_file(3, vec![2])], vec![1]),
+            edit(vec![new_file(4, vec![3])], vec![2]),
+        ];
+        let mut files = FileSet::from_edits(&edits);
+        assert!(!files.is_live(2));
+        assert_eq!(files.num_referrers(2), 1);
+        assert_eq!(files.cleanup(), vec![1]);
+
+        // File 3 is deleted but still referenced by file 4, which keeps file 2
+        // alive as well.
+        files.apply(&edit(vec![], vec![3]));
+        assert_eq!(files.cleanup(), Vec::<u32>::new());
+
+        // Once the referrer is compacted away, both files are deleted.
+        files.apply(&edit(vec![new_file(5, vec![])], vec![4]));
+        assert_eq!(files.cleanup(), vec![2, 3, 4]);
+        assert!(files.is_live(5));
+        assert_eq!(files.cleanup(), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn compact_all_dead_file() {
+        let mut files =
+            FileSet::from_edits(&[edit(vec![new_file(1, vec![]), new_file(2, vec![])], vec![])]);
+        // Every page of file 1 is dead, the compaction outputs no file.
+        files.apply(&edit(vec![], vec![1]));
+        assert_eq!(files.live_files().count(), 1);
+        assert!(files.is_live(2));
+        assert_eq!(files.cleanup(), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn recover_from_manifest() {
+        let base = tempdir::TempDir::new("file_set_recover").unwrap();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let num_edits = 20000u32;
+        let edits: Vec<_> = (0..num_edits)
+            .map(|id| edit(vec![new_file(id, vec![])], id.checked_sub(100).into_iter().collect()))
+            .collect();
+        manifest.record_batch(edits, |_| VersionEdit::default()).await.unwrap();
+
+        let (files, stats) = FileSet::recover(&manifest).await.unwrap();
+        // The base snapshot and the edits.
+        assert_eq!(stats.edits, num_edits as u64 + 1);
+        assert_eq!(stats.bytes, std::fs::metadata(base.path().join("MANIFEST_1")).unwrap().len());
+
+        let expect = FileSet::from_edits(&manifest.list_versions().await.unwrap());
+        let ids = |files: &FileSet| files.live_files().map(|f| f.id).collect::<Vec<_>>();
+        assert_eq!(ids(&files), ids(&expect));
+        assert_eq!(ids(&files), (num_edits - 100..num_edits).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn recover_seeds_lsn() {
+        use crate::table::lsn::LsnAllocator;
+
+        let base = tempdir::TempDir::new("file_set_lsn").unwrap();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let with_lsn = |id, max_lsn| NewFile {
+            max_lsn: Some(max_lsn),
+            ..new_file(id, vec![])
+        };
+        let edits = vec![
+            edit(vec![with_lsn(1, 30), with_lsn(2, 42)], vec![]),
+            // The file with the largest LSN is obsolete but still on disk.
+            edit(vec![with_lsn(3, 40), new_file(4, vec![2])], vec![2]),
+        ];
+        manifest.record_batch(edits, |_| VersionEdit::default()).await.unwrap();
+        drop(manifest);
+
+        let manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let (files, _) = FileSet::recover(&manifest).await.unwrap();
+        assert_eq!(files.max_lsn(), 42);
+        let lsns = LsnAllocator::new(files.max_lsn());
+        assert_eq!(lsns.next(), 43);
+        assert_eq!(FileSet::default().max_lsn(), 0);
+    }
+
+    #[tokio::test]
+    async fn recover_across_snapshot() {
+        let base = tempdir::TempDir::new("file_set_snapshot").unwrap();
+        let mut manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let with_lsn = |id, max_lsn, referenced_files| NewFile {
+            max_lsn: Some(max_lsn),
+            ..new_file(id, referenced_files)
+        };
+        let edits = vec![
+            edit(vec![with_lsn(1, 30, vec![]), with_lsn(2, 42, vec![])], vec![]),
+            // File 2 is obsolete but still referenced by file 3.
+            edit(vec![with_lsn(3, 40, vec![2])], vec![2]),
+        ];
+        manifest.record_batch(edits.clone(), |_| VersionEdit::default()).await.unwrap();
+        let files = FileSet::from_edits(&edits);
+        manifest.snapshot(|| files.snapshot_edit()).await.unwrap();
+        drop(manifest);
+
+        let manifest = Manifest::open(base.as_ref()).await.unwrap();
+        let (mut files, stats) = FileSet::recover(&manifest).await.unwrap();
+        // Only the snapshot is replayed.
+        assert_eq!(stats.edits, 1);
+        assert_eq!(files.live_files().map(|f| f.id).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(!files.is_live(2));
+        assert_eq!(files.num_referrers(2), 1);
+        assert_eq!(files.max_lsn(), 42);
+        assert_eq!(files.cleanup(), Vec::<u32>::new());
+        files.apply(&edit(vec![], vec![3]));
+        assert_eq!(files.cleanup(), vec![2, 3]);
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::error::{Error, Result};
+
+/// 供外部事务使用的 key / range 锁.
+///
+/// 数据表自身的写入路径不会获取这些锁, 只有显式使用的调用方才需要付出代价.
+/// 等待者按照 FIFO 的顺序被唤醒, 通过超时来避免死锁.
+#[derive(Default)]
+pub struct LockManager {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    closed: bool,
+    next_id: u64,
+    held: Vec<(u64, LockRange)>,
+    waiters: VecDeque<Waiter>,
+}
+
+struct Waiter {
+    id: u64,
+    range: LockRange,
+    tx: oneshot::Sender<Result<()>>,
+}
+
+/// A key range `[start, end)`, `end = None` means unbounded.
+#[derive(Clone, Debug)]
+struct LockRange {
+    start: Vec<u8>,
+    end: Option<Vec<u8>>,
+}
+
+impl LockRange {
+    fn key(key: &[u8]) -> Self {
+        // The smallest key after `key`.
+        let mut end = key.to_vec();
+        end.push(0);
+        Self {
+            start: key.to_vec(),
+            end: Some(end),
+        }
+    }
+
+    fn overlaps(&self, other: &LockRange) -> bool {
+        let before_end = |start: &[u8], end: &Option<Vec<u8>>| match end {
+            Some(end) => start < end.as_slice(),
+            None => true,
+        };
+        before_end(&self.start, &other.end) && before_end(&other.start, &self.end)
+    }
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks a single key, waits at most `timeout` for the lock.
+    pub async fn lock_key(&self, key: &[u8], timeout: Duration) -> Result<LockGuard<'_>> {
+        self.lock(LockRange::key(key), timeout).await
+    }
+
+    /// Locks the keys in `[start, end)`, waits at most `timeout` for the lock.
+    pub async fn lock_range(
+        &self,
+        start: &[u8],
+        end: Option<&[u8]>,
+        timeout: Duration,
+    ) -> Result<LockGuard<'_>> {
+        let range = LockRange {
+            start: start.to_vec(),
+            end: end.map(|end| end.to_vec()),
+        };
+        self.lock(range, timeout).await
+    }
+
+    /// Closes the lock manager, all waiters are woken with [`Error::Closed`].
+    ///
+    /// Locks that are already held are still released when their guards are
+    /// dropped.
+    pub fn close(&self) {
+        let mut inner = self.inner();
+        inner.closed = true;
+        for waiter in inner.waiters.drain(..) {
+            let _ = waiter.tx.send(Err(Error::Closed));
+        }
+    }
+
+    async fn lock(&self, range: LockRange, timeout: Duration) -> Result<LockGuard<'_>> {
+        let (id, rx) = {
+            let mut inner = self.inner();
+            if inner.closed {
+                return Err(Error::Closed);
+            }
+            let id = inner.next_id;
+            inner.next_id += 1;
+            // 不能越过更早的等待者, 以保证公平.
+            if !inner.conflicts(&range, inner.waiters.len()) {
+                inner.held.push((id, range));
+                return Ok(LockGuard { manager: self, id });
+            }
+            let (tx, rx) = oneshot::channel();
+            inner.waiters.push_back(Waiter { id, range, tx });
+            (id, rx)
+        };
+
+        // Releases the lock if this future is dropped after it is granted.
+        let mut pending = PendingLock {
+            manager: self,
+            id,
+            rx: Some(rx),
+        };
+        let result = tokio::time::timeout(timeout, pending.rx.as_mut().unwrap()).await;
+        pending.rx = None;
+        match result {
+            Ok(Ok(result)) => result.map(|_| LockGuard { manager: self, id }),
+            Ok(Err(_)) => Err(Error::Closed),
+            Err(_) => {
+                let mut inner = self.inner();
+                if let Some(pos) = inner.waiters.iter().position(|w| w.id == id) {
+                    inner.waiters.remove(pos);
+                    // 移除的等待者可能阻塞了后面的等待者.
+                    inner.wake();
+                    Err(Error::LockTimeout)
+                } else if inner.held.iter().any(|(held, _)| *held == id) {
+                    // The lock is granted right after the timeout.
+                    Ok(LockGuard { manager: self, id })
+                } else if inner.closed {
+                    Err(Error::Closed)
+                } else {
+                    // The grant is dropped since the receiver is gone.
+                    Err(Error::LockTimeout)
+                }
+            }
+        }
+    }
+
+    fn unlock(&self, id: u64) {
+        let mut inner = self.inner();
+        inner.held.retain(|(held, _)| *held != id);
+        inner.wake();
+    }
+
+    /// Removes a waiter that is not granted yet.
+    fn cancel(&self, id: u64) {
+        let mut inner = self.inner();
+        if let Some(pos) = inner.waiters.iter().position(|w| w.id == id) {
+            inner.waiters.remove(pos);
+            inner.wake();
+        }
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        // The inner state is always consistent, even if a holder panicked.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    #[cfg(test)]
+    fn num_waiters(&self) -> usize {
+        self.inner().waiters.len()
+    }
+
+    #[cfg(test)]
+    fn num_held(&self) -> usize {
+        self.inner().held.len()
+    }
+}
+
+impl Inner {
+    /// Returns true if `range` conflicts with a held lock or one of the first
+    /// `num_waiters` waiters.
+    fn conflicts(&self, range: &LockRange, num_waiters: usize) -> bool {
+        self.held.iter().any(|(_, held)| held.overlaps(range))
+            || self
+                .waiters
+                .iter()
+                .take(num_waiters)
+                .any(|waiter| waiter.range.overlaps(range))
+    }
+
+    /// Grants the waiters that no longer conflict, in FIFO order.
+    fn wake(&mut self) {
+        let mut i = 0;
+        while i < self.waiters.len() {
+            if self.conflicts(&self.waiters[i].range, i) {
+                i += 1;
+                continue;
+            }
+            let waiter = self.waiters.remove(i).unwrap();
+            if waiter.tx.send(Ok(())).is_ok() {
+                self.held.push((waiter.id, waiter.range));
+            }
+        }
+    }
+}
+
+/// A lock being waited for, the lock is released or the waiter is removed if
+/// it is dropped before the grant is received.
+struct PendingLock<'a> {
+    manager: &'a LockManager,
+    id: u64,
+    rx: Option<oneshot::Receiver<Result<()>>>,
+}
+
+impl Drop for PendingLock<'_> {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            // No lock can be granted after the receiver is closed.
+            rx.close();
+            if let Ok(Ok(())) = rx.try_recv() {
+                self.manager.unlock(self.id);
+            } else {
+                self.manager.cancel(self.id);
+            }
+        }
+    }
+}
+
+/// A guard that releases the lock when dropped.
+pub struct LockGuard<'a> {
+    manager: &'a LockManager,
+    id: u64,
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        self.manager.unlock(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn lock_range_overlaps() {
+        let a = LockRange::key(b"a");
+        let b = LockRange::key(b"b");
+        assert!(a.overlaps(&a));
+        assert!(!a.overlaps(&b));
+        let range = LockRange {
+            start: b"a".to_vec(),
+            end: Some(b"b".to_vec()),
+        };
+        assert!(range.overlaps(&a));
+        assert!(!range.overlaps(&b));
+        let full = LockRange {
+            start: vec![],
+            end: None,
+        };
+        assert!(full.overlaps(&a));
+        assert!(full.overlaps(&range));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn lock_prevents_lost_updates() {
+        let manager = Arc::new(LockManager::new());
+        let store = Arc::new(Mutex::new(HashMap::<Vec<u8>, u64>::new()));
+        let mut tasks = Vec::new();
+        for i in 0..32 {
+            let manager = manager.clone();
+            let store = store.clone();
+            tasks.push(tokio::spawn(async move {
+                for j in 0..50 {
+                    let key = [(i + j) as u8 % 4];
+                    let _guard = if j % 5 == 0 {
+                        manager.lock_range(&[0], Some(&[2]), TIMEOUT).await.unwrap()
+                    } else {
+                        manager.lock_key(&key, TIMEOUT).await.unwrap()
+                    };
+                    let key = if j % 5 == 0 { [j as u8 % 2] } else { key };
+                    let value = store.lock().unwrap().get(key.as_slice()).copied();
+                    tokio::task::yield_now().await;
+                    store.lock().unwrap().insert(key.to_vec(), value.unwrap_or(0) + 1);
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let total: u64 = store.lock().unwrap().values().sum();
+        assert_eq!(total, 32 * 50);
+        assert_eq!(manager.num_held(), 0);
+        assert_eq!(manager.num_waiters(), 0);
+    }
+
+    #[tokio::test]
+    async fn lock_timeout() {
+        let manager = LockManager::new();
+        let guard = manager.lock_key(b"a", TIMEOUT).await.unwrap();
+        let err = manager
+            .lock_range(b"", None, Duration::from_millis(10))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::LockTimeout));
+        assert_eq!(manager.num_waiters(), 0);
+        // Other keys are not blocked.
+        let _other = manager.lock_key(b"b", Duration::from_millis(10)).await.unwrap();
+        drop(guard);
+        let _guard = manager.lock_key(b"a", Duration::from_millis(10)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lock_fifo_and_panic() {
+        let manager = Arc::new(LockManager::new());
+        let guard = manager.lock_key(b"a", TIMEOUT).await.unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let task_manager = manager.clone();
+            let tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let _guard = task_manager.lock_key(b"a", TIMEOUT).await.unwrap();
+                tx.send(i).unwrap();
+                if i == 1 {
+                    panic!("release on panic");
+                }
+            }));
+            while manager.num_waiters() <= i {
+                tokio::task::yield_now().await;
+            }
+        }
+        drop(guard);
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await.is_err(), i == 1);
+        }
+        for i in 0..4 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        assert_eq!(manager.num_held(), 0);
+    }
+
+    #[tokio::test]
+    async fn cancelled_lock_is_released() {
+        let manager = LockManager::new();
+        let guard = manager.lock_key(b"a", TIMEOUT).await.unwrap();
+        let mut waiting = Box::pin(manager.lock_key(b"a", TIMEOUT));
+        assert!(tokio::time::timeout(Duration::from_millis(1), &mut waiting).await.is_err());
+        assert_eq!(manager.num_waiters(), 1);
+        // The lock is granted to the waiter, which is dropped before it
+        // receives the grant.
+        drop(guard);
+        assert_eq!(manager.num_held(), 1);
+        drop(waiting);
+        assert_eq!(manager.num_held(), 0);
+        let _guard = manager.lock_key(b"a", Duration::from_millis(10)).await.unwrap();
+
+        // A waiter dropped before the grant is removed.
+        let mut waiting = Box::pin(manager.lock_key(b"a", TIMEOUT));
+        assert!(tokio::time::timeout(Duration::from_millis(1), &mut waiting).await.is_err());
+        drop(waiting);
+        assert_eq!(manager.num_waiters(), 0);
+    }
+
+    #[tokio::test]
+    async fn close_wakes_waiters() {
+        let manager = Arc::new(LockManager::new());
+        let guard = manager.lock_key(b"a", TIMEOUT).await.unwrap();
+        let mut tasks = Vec::new();
+        for _ in 0..8 {
+            let manager = manager.clone();
+            tasks.push(tokio::spawn(async move {
+                manager.lock_key(b"a", TIMEOUT).await.map(|_| ())
+            }));
+        }
+        while manager.num_waiters() < 8 {
+            tokio::task::yield_now().await;
+        }
+        manager.close();
+        for task in tasks {
+            assert!(matches!(task.await.unwrap(), Err(Error::Closed)));
+        }
+        assert_eq!(manager.num_waiters(), 0);
+        assert!(matches!(
+            manager.lock_key(b"b", TIMEOUT).await.err().unwrap(),
+            Error::Closed
+        ));
+        drop(guard);
+        assert_eq!(manager.num_held(), 0);
+    }
+}
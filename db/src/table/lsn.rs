@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::error::{Error, Result};
+
+/// Assigns LSNs from a monotonic counter.
+///
+/// LSNs order the versions of a key, so they are never derived from the wall
+/// clock, which may jump backward. The counter is seeded on open with the
+/// largest LSN recovered from the manifest and the WAL, so every assigned LSN
+/// is strictly greater than any recovered one.
+pub struct LsnAllocator {
+    /// The last assigned or recovered LSN.
+    last: AtomicU64,
+    /// The first LSN assigned since open, `u64::MAX` if none.
+    first_assigned: AtomicU64,
+}
+
+impl LsnAllocator {
+    /// Creates an allocator whose LSNs are greater than `recovered`.
+    pub fn new(recovered: u64) -> Self {
+        Self {
+            last: AtomicU64::new(recovered),
+            first_assigned: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Assigns a new LSN.
+    pub fn next(&self) -> u64 {
+        let lsn = self.last.fetch_add(1, Ordering::AcqRel) + 1;
+        self.first_assigned.fetch_min(lsn, Ordering::AcqRel);
+        lsn
+    }
+
+    /// Returns the last assigned or recovered LSN.
+    pub fn last(&self) -> u64 {
+        self.last.load(Ordering::Acquire)
+    }
+
+    /// Advances the counter past an LSN recovered after the allocator is
+    /// created, e.g. from a WAL record.
+    ///
+    /// Returns [`Error::LsnRegression`] if the LSN is not less than an LSN
+    /// already assigned, since the assigned versions would no longer be the
+    /// newest.
+    pub fn recover(&self, lsn: u64) -> Result<()> {
+        if lsn >= self.first_assigned.load(Ordering::Acquire) {
+            return Err(Error::LsnRegression(lsn));
+        }
+        self.last.fetch_max(lsn, Ordering::AcqRel);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lsn_allocator() {
+        let lsns = LsnAllocator::new(42);
+        assert_eq!(lsns.last(), 42);
+        lsns.recover(40).unwrap();
+        lsns.recover(50).unwrap();
+        assert_eq!(lsns.last(), 50);
+        assert_eq!(lsns.next(), 51);
+        assert_eq!(lsns.next(), 52);
+
+        // Older LSNs are fine, but a recovered LSN must not collide with or
+        // overtake an assigned one.
+        lsns.recover(50).unwrap();
+        assert!(matches!(lsns.recover(51), Err(Error::LsnRegression(51))));
+        assert!(matches!(lsns.recover(60), Err(Error::LsnRegression(60))));
+        assert_eq!(lsns.next(), 53);
+    }
+}
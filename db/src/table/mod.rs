@@ -1,3 +1,9 @@
+pub mod lock;
+pub mod lsn;
+pub mod partition;
+pub mod snapshot;
+pub mod suspend;
+
 use anyhow::Result;
 
 use std::path::Path;
@@ -0,0 +1,77 @@
+/// 将 key 映射到分区, 数据表按照分区维护各自的树.
+///
+/// 分区数量和名称会记录到 manifest 中, 创建后不可修改, 因此映射必须是确定的,
+/// 不能依赖进程内的随机种子.
+pub trait Partitioner: Send + Sync {
+    /// 记录到 manifest 中的名称, 重新打开时用来检查是否是同一个分区器.
+    fn name(&self) -> &str;
+
+    /// 分区数量, 至少为 1.
+    fn num_partitions(&self) -> u32;
+
+    /// 返回 `key` 所在的分区, 小于 [`Self::num_partitions`].
+    fn partition(&self, key: &[u8]) -> u32;
+}
+
+/// 按照 key 的 FNV-1a 哈希均匀分区.
+///
+/// 哈希分区打散了 key 的顺序, 范围扫描需要合并所有分区.
+pub struct HashPartitioner {
+    num_partitions: u32,
+}
+
+impl HashPartitioner {
+    pub fn new(num_partitions: u32) -> Self {
+        assert!(num_partitions > 0, "num_partitions must be positive");
+        Self { num_partitions }
+    }
+}
+
+impl Partitioner for HashPartitioner {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    fn num_partitions(&self) -> u32 {
+        self.num_partitions
+    }
+
+    fn partition(&self, key: &[u8]) -> u32 {
+        (fnv1a(key) % self.num_partitions as u64) as u32
+    }
+}
+
+/// 跨版本和进程稳定的哈希.
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    data.iter()
+        .fold(OFFSET_BASIS, |hash, b| (hash ^ *b as u64).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_partitioner_routing() {
+        let partitioner = HashPartitioner::new(8);
+        assert_eq!(partitioner.name(), "hash");
+        assert_eq!(partitioner.num_partitions(), 8);
+        // The mapping is persisted, so it must never change.
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+        let mut counts = [0; 8];
+        for i in 0..8000u32 {
+            let key = format!("key{i}");
+            let partition = partitioner.partition(key.as_bytes());
+            assert_eq!(partition, partitioner.partition(key.as_bytes()));
+            counts[partition as usize] += 1;
+        }
+        assert!(counts.iter().all(|&n| n > 800 && n < 1200), "{counts:?}");
+
+        let single = HashPartitioner::new(1);
+        assert_eq!(single.partition(b"any"), 0);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::utils::trace::log_warn;
+
+/// The default LSN gap between the current LSN and the GC floor beyond which
+/// the oldest snapshot is reported as lagging.
+const DEFAULT_LAG_WARNING_LSNS: u64 = 1 << 20;
+
+/// Tracks the LSNs pinned by the live snapshots of a table.
+///
+/// Versions older than the GC floor, i.e. the oldest pinned LSN, are not
+/// visible to any snapshot once a newer version of the same key exists, so
+/// they can be collected. A snapshot that is never dropped holds the floor
+/// back forever, [`SnapshotStats::lagging`] reports it.
+pub struct SnapshotList {
+    /// lsn -> the number of snapshots at the lsn
+    pinned: Mutex<BTreeMap<u64, usize>>,
+    lag_warning_lsns: u64,
+}
+
+impl Default for SnapshotList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SnapshotList {
+    pub fn new() -> Self {
+        Self::with_lag_warning(DEFAULT_LAG_WARNING_LSNS)
+    }
+
+    /// Creates a list that reports lagging when the floor falls more than
+    /// `lsns` behind the current LSN.
+    pub fn with_lag_warning(lsns: u64) -> Self {
+        Self {
+            pinned: Mutex::default(),
+            lag_warning_lsns: lsns,
+        }
+    }
+
+    /// Pins `lsn` until the returned snapshot is dropped.
+    pub fn acquire(&self, lsn: u64) -> Snapshot<'_> {
+        *self.pinned().entry(lsn).or_default() += 1;
+        Snapshot { list: self, lsn }
+    }
+
+    /// Returns the oldest LSN pinned by a live snapshot, or `current_lsn` if
+    /// there is no snapshot.
+    pub fn gc_floor_lsn(&self, current_lsn: u64) -> u64 {
+        self.pinned()
+            .keys()
+            .next()
+            .map_or(current_lsn, |lsn| (*lsn).min(current_lsn))
+    }
+
+    pub fn stats(&self, current_lsn: u64) -> SnapshotStats {
+        let num_snapshots = self.pinned().values().sum();
+        let gc_floor_lsn = self.gc_floor_lsn(current_lsn);
+        let lag = current_lsn - gc_floor_lsn;
+        let lagging = lag > self.lag_warning_lsns;
+        if lagging {
+            log_warn!(gc_floor_lsn, current_lsn, "the oldest snapshot holds back GC, is it leaked?");
+        }
+        SnapshotStats {
+            num_snapshots,
+            gc_floor_lsn,
+            lag,
+            lagging,
+        }
+    }
+
+    fn release(&self, lsn: u64) {
+        let mut pinned = self.pinned();
+        let count = pinned.get_mut(&lsn).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            pinned.remove(&lsn);
+        }
+    }
+
+    fn pinned(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, usize>> {
+        self.pinned.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// A live snapshot, it unpins its LSN when dropped.
+pub struct Snapshot<'a> {
+    list: &'a SnapshotList,
+    lsn: u64,
+}
+
+impl Snapshot<'_> {
+    pub fn lsn(&self) -> u64 {
+        self.lsn
+    }
+}
+
+impl Drop for Snapshot<'_> {
+    fn drop(&mut self) {
+        self.list.release(self.lsn);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotStats {
+    pub num_snapshots: usize,
+    /// Versions below this LSN are collectable once shadowed.
+    pub gc_floor_lsn: u64,
+    /// The gap between the current LSN and the GC floor.
+    pub lag: u64,
+    /// True if the lag exceeds the warning threshold, which usually means a
+    /// snapshot is leaked.
+    pub lagging: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gc_floor_moves_with_snapshots() {
+        let list = SnapshotList::new();
+        assert_eq!(list.gc_floor_lsn(10), 10);
+
+        let s1 = list.acquire(3);
+        let s2 = list.acquire(5);
+        let s3 = list.acquire(3);
+        assert_eq!(s1.lsn(), 3);
+        assert_eq!(list.gc_floor_lsn(10), 3);
+        drop(s1);
+        // Another snapshot still pins lsn 3.
+        assert_eq!(list.gc_floor_lsn(10), 3);
+        drop(s3);
+        assert_eq!(list.gc_floor_lsn(10), 5);
+        assert_eq!(list.stats(10).num_snapshots, 1);
+        drop(s2);
+        assert_eq!(list.gc_floor_lsn(12), 12);
+        assert_eq!(list.stats(12).num_snapshots, 0);
+    }
+
+    #[test]
+    fn leaked_snapshot_holds_back_floor() {
+        let list = SnapshotList::with_lag_warning(100);
+        std::mem::forget(list.acquire(7));
+        for lsn in 8..50 {
+            drop(list.acquire(lsn));
+        }
+        let stats = list.stats(100);
+        assert_eq!(stats.gc_floor_lsn, 7);
+        assert_eq!(stats.lag, 93);
+        assert!(!stats.lagging);
+        let stats = list.stats(1000);
+        assert_eq!(stats.gc_floor_lsn, 7);
+        assert!(stats.lagging);
+        assert_eq!(stats.num_snapshots, 1);
+    }
+}
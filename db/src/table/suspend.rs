@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::{oneshot, Notify};
+
+use crate::error::{Error, Result};
+
+/// Admission control to suspend the writes of a table, e.g. during an online
+/// migration.
+///
+/// Every write holds a [`WritePermit`] while it runs. While a [`SuspendGuard`]
+/// is alive, new writes fail with [`Error::Suspended`], or wait in a bounded
+/// FIFO queue if the guard allows it, and are admitted in order once the last
+/// guard is dropped. Reads don't go through the gate.
+#[derive(Default)]
+pub struct WriteGate {
+    inner: Mutex<Inner>,
+    drained: Notify,
+}
+
+#[derive(Default)]
+struct Inner {
+    closed: bool,
+    /// The number of alive suspend guards.
+    suspended: usize,
+    /// The maximum number of queued writes while suspended.
+    queue_limit: usize,
+    in_flight: usize,
+    queued: VecDeque<oneshot::Sender<Result<()>>>,
+}
+
+impl WriteGate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Admits a write.
+    ///
+    /// Returns [`Error::Suspended`] if writes are suspended and the queue is
+    /// full, or [`Error::Closed`] if the gate is closed.
+    pub async fn admit(&self) -> Result<WritePermit<'_>> {
+        let rx = {
+            let mut inner = self.inner();
+            if inner.closed {
+                return Err(Error::Closed);
+            }
+            if inner.suspended == 0 {
+                inner.in_flight += 1;
+                return Ok(WritePermit { gate: self });
+            }
+            if inner.queued.len() >= inner.queue_limit {
+                return Err(Error::Suspended);
+            }
+            let (tx, rx) = oneshot::channel();
+            inner.queued.push_back(tx);
+            rx
+        };
+        let mut waiter = Waiter {
+            gate: self,
+            rx: Some(rx),
+        };
+        let result = waiter.rx.as_mut().unwrap().await;
+        waiter.rx = None;
+        match result {
+            // The permit is counted when the write is dequeued.
+            Ok(Ok(())) => Ok(WritePermit { gate: self }),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(Error::Closed),
+        }
+    }
+
+    /// Suspends writes, waits for the admitted writes to finish.
+    ///
+    /// Suspensions are reference-counted, writes resume when all guards are
+    /// dropped.
+    pub async fn suspend_writes(&self) -> SuspendGuard<'_> {
+        self.suspend(0).await
+    }
+
+    /// Like [`Self::suspend_writes`], but up to `queue_limit` writes wait for
+    /// the resumption instead of failing.
+    pub async fn suspend_writes_queued(&self, queue_limit: usize) -> SuspendGuard<'_> {
+        self.suspend(queue_limit).await
+    }
+
+    /// Closes the gate, queued and new writes fail with [`Error::Closed`].
+    ///
+    /// Closing while writes are suspended is allowed.
+    pub fn close(&self) {
+        let mut inner = self.inner();
+        inner.closed = true;
+        for tx in inner.queued.drain(..) {
+            let _ = tx.send(Err(Error::Closed));
+        }
+    }
+
+    async fn suspend(&self, queue_limit: usize) -> SuspendGuard<'_> {
+        {
+            let mut inner = self.inner();
+            inner.suspended += 1;
+            inner.queue_limit = inner.queue_limit.max(queue_limit);
+        }
+        let guard = SuspendGuard { gate: self };
+        loop {
+            let drained = self.drained.notified();
+            if self.inner().in_flight == 0 {
+                return guard;
+            }
+            drained.await;
+        }
+    }
+
+    fn resume(&self) {
+        let mut inner = self.inner();
+        inner.suspended -= 1;
+        if inner.suspended > 0 {
+            return;
+        }
+        inner.queue_limit = 0;
+        while let Some(tx) = inner.queued.pop_front() {
+            if tx.send(Ok(())).is_ok() {
+                inner.in_flight += 1;
+            }
+        }
+    }
+
+    fn release(&self) {
+        let mut inner = self.inner();
+        inner.in_flight -= 1;
+        if inner.in_flight == 0 {
+            self.drained.notify_waiters();
+        }
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    #[cfg(test)]
+    fn num_queued(&self) -> usize {
+        self.inner().queued.len()
+    }
+}
+
+/// An admitted write, the write is finished when the permit is dropped.
+pub struct WritePermit<'a> {
+    gate: &'a WriteGate,
+}
+
+impl Drop for WritePermit<'_> {
+    fn drop(&mut self) {
+        self.gate.release();
+    }
+}
+
+/// Releases the permit if it is given to a queued write that is cancelled
+/// before receiving it.
+struct Waiter<'a> {
+    gate: &'a WriteGate,
+    rx: Option<oneshot::Receiver<Result<()>>>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if let Ok(Ok(())) = rx.try_recv() {
+                self.gate.release();
+            }
+        }
+    }
+}
+
+/// A guard that resumes writes when dropped.
+pub struct SuspendGuard<'a> {
+    gate: &'a WriteGate,
+}
+
+impl SuspendGuard<'_> {
+    /// Resumes writes, the same as dropping the guard.
+    pub fn resume(self) {}
+}
+
+impl Drop for SuspendGuard<'_> {
+    fn drop(&mut self) {
+        self.gate.resume();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn suspend_and_resume() {
+        let gate = Arc::new(WriteGate::new());
+        let acked = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for i in 0..4u64 {
+            let gate = gate.clone();
+            let acked = acked.clone();
+            tasks.push(tokio::spawn(async move {
+                let mut suspended = 0;
+                let mut j = 0;
+                while j < 200 {
+                    match gate.admit().await {
+                        Ok(_permit) => {
+                            acked.lock().unwrap().push((i, j));
+                            j += 1;
+                        }
+                        Err(Error::Suspended) => suspended += 1,
+                        Err(err) => panic!("{err}"),
+                    }
+                    tokio::task::yield_now().await;
+                }
+                suspended
+            }));
+        }
+
+        let guard = gate.suspend_writes().await;
+        // In-flight writes are drained, no write is admitted while suspended.
+        let len = acked.lock().unwrap().len();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(acked.lock().unwrap().len(), len);
+        // Suspensions are reference-counted.
+        let nested = gate.suspend_writes().await;
+        drop(guard);
+        assert!(matches!(gate.admit().await, Err(Error::Suspended)));
+        nested.resume();
+
+        let mut suspended = 0;
+        for task in tasks {
+            suspended += task.await.unwrap();
+        }
+        assert!(suspended > 0);
+        // Every write is acknowledged exactly once, in order for each writer.
+        let acked = acked.lock().unwrap();
+        assert_eq!(acked.len(), 4 * 200);
+        for i in 0..4 {
+            let writes: Vec<_> = acked.iter().filter(|(w, _)| *w == i).map(|(_, j)| *j).collect();
+            assert_eq!(writes, (0..200).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_writes_in_order() {
+        let gate = Arc::new(WriteGate::new());
+        let guard = gate.suspend_writes_queued(4).await;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::new();
+        for i in 0..4 {
+            let task_gate = gate.clone();
+            let tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = task_gate.admit().await.unwrap();
+                tx.send(i).unwrap();
+            }));
+            while gate.num_queued() <= i {
+                tokio::task::yield_now().await;
+            }
+        }
+        // The queue is full.
+        assert!(matches!(gate.admit().await, Err(Error::Suspended)));
+        drop(guard);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        for i in 0..4 {
+            assert_eq!(rx.recv().await, Some(i));
+        }
+        drop(gate.admit().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn cancel_queued_write() {
+        let gate = WriteGate::new();
+        let guard = gate.suspend_writes_queued(2).await;
+        let mut admitted = Box::pin(gate.admit());
+        let mut cancelled = Box::pin(gate.admit());
+        assert!(tokio::time::timeout(Duration::from_millis(1), &mut admitted).await.is_err());
+        assert!(tokio::time::timeout(Duration::from_millis(1), &mut cancelled).await.is_err());
+        assert_eq!(gate.num_queued(), 2);
+        // Both writes are dequeued, one is cancelled before receiving it.
+        drop(guard);
+        drop(cancelled);
+        let permit = admitted.await.unwrap();
+        drop(permit);
+        // Nothing is left in flight.
+        tokio::time::timeout(Duration::from_secs(1), gate.suspend_writes())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn close_while_suspended() {
+        let gate = Arc::new(WriteGate::new());
+        let guard = gate.suspend_writes_queued(1).await;
+        let task_gate = gate.clone();
+        let task = tokio::spawn(async move { task_gate.admit().await.map(|_| ()) });
+        while gate.num_queued() == 0 {
+            tokio::task::yield_now().await;
+        }
+        gate.close();
+        assert!(matches!(task.await.unwrap(), Err(Error::Closed)));
+        drop(guard);
+        assert!(matches!(gate.admit().await, Err(Error::Closed)));
+    }
+}
@@ -0,0 +1,83 @@
+/// A fixed-size bitmap, used to record which pages of a page group are
+/// deallocated.
+#[derive(Clone, Debug, Default)]
+pub struct FixedBitmap {
+    len: u32,
+    free: u32,
+    bits: Vec<u64>,
+}
+
+impl FixedBitmap {
+    pub fn new(len: u32) -> Self {
+        let num_words = (len as usize).div_ceil(64);
+        Self {
+            len,
+            free: len,
+            bits: vec![0; num_words],
+        }
+    }
+
+    /// Sets the bit at `index`, returns true if the bit is set by this call.
+    pub fn set(&mut self, index: u32) -> bool {
+        assert!(index < self.len);
+        let (word, mask) = Self::locate(index);
+        if self.bits[word] & mask != 0 {
+            return false;
+        }
+        self.bits[word] |= mask;
+        self.free -= 1;
+        true
+    }
+
+    /// Returns whether the bit at `index` is set.
+    pub fn test(&self, index: u32) -> bool {
+        assert!(index < self.len);
+        let (word, mask) = Self::locate(index);
+        self.bits[word] & mask != 0
+    }
+
+    /// Returns the number of bits.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Returns the number of unset bits.
+    pub fn free(&self) -> u32 {
+        self.free
+    }
+
+    /// Returns whether all bits are set.
+    pub fn is_full(&self) -> bool {
+        self.free == 0
+    }
+
+    /// Returns an iterator over the indexes of the unset bits.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).filter(move |&i| !self.test(i))
+    }
+
+    fn locate(index: u32) -> (usize, u64) {
+        ((index / 64) as usize, 1 << (index % 64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_bitmap() {
+        let mut bitmap = FixedBitmap::new(100);
+        assert_eq!(bitmap.len(), 100);
+        assert_eq!(bitmap.free(), 100);
+        assert!(bitmap.set(0));
+        assert!(bitmap.set(64));
+        assert!(bitmap.set(99));
+        assert!(!bitmap.set(64));
+        assert!(bitmap.test(64));
+        assert!(!bitmap.test(65));
+        assert_eq!(bitmap.free(), 97);
+        assert_eq!(bitmap.iter().count(), 97);
+        assert!(!bitmap.is_full());
+    }
+}
@@ -0,0 +1,126 @@
+use std::hash::Hasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rustc_hash::FxHasher;
+
+/// A Bloom filter of raw keys, which can be updated concurrently.
+///
+/// Keys can't be removed from a Bloom filter, so a deleted key stays in it and
+/// is resolved by its tombstone. [`Self::may_contain`] returning false means
+/// the key was never inserted.
+#[derive(Debug)]
+pub struct BloomFilter {
+    bits: Box<[AtomicU64]>,
+    num_probes: u32,
+}
+
+impl BloomFilter {
+    /// Creates a filter sized for `num_keys` keys with `bits_per_key` bits
+    /// for each key.
+    ///
+    /// 10 bits per key gives a false positive rate of about 1%.
+    pub fn with_estimated_keys(num_keys: usize, bits_per_key: usize) -> Self {
+        let num_bits = (num_keys * bits_per_key).max(64);
+        let bits = (0..num_bits.div_ceil(64)).map(|_| AtomicU64::new(0)).collect();
+        // k = ln(2) * m / n minimizes the false positive rate.
+        let num_probes = (bits_per_key as f64 * std::f64::consts::LN_2).round() as u32;
+        Self {
+            bits,
+            num_probes: num_probes.clamp(1, 30),
+        }
+    }
+
+    /// Returns the number of bits.
+    pub fn num_bits(&self) -> u64 {
+        self.bits.len() as u64 * 64
+    }
+
+    pub fn insert(&self, key: &[u8]) {
+        for bit in self.probes(key) {
+            self.bits[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns false if `key` is definitely not in the filter.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.probes(key).all(|bit| {
+            let word = self.bits[(bit / 64) as usize].load(Ordering::Relaxed);
+            word & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Adds all keys of `other` to this filter.
+    ///
+    /// Both filters must be created with the same size and probes, e.g. to
+    /// merge a filter built for a flushed file into the table's filter.
+    pub fn merge(&self, other: &BloomFilter) {
+        assert_eq!(self.bits.len(), other.bits.len());
+        assert_eq!(self.num_probes, other.num_probes);
+        for (word, other) in self.bits.iter().zip(other.bits.iter()) {
+            word.fetch_or(other.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    /// Removes all keys, e.g. before rebuilding the filter after compaction.
+    pub fn clear(&self) {
+        for word in self.bits.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the bits of `key` with double hashing.
+    fn probes(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let hash = hash(key);
+        let num_bits = self.num_bits();
+        let h1 = hash as u32 as u64;
+        let h2 = (hash >> 32) | 1;
+        (0..self.num_probes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+fn hash(key: &[u8]) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(key);
+    // FxHash mixes poorly on its own, finalize it like MurmurHash3.
+    let mut h = hasher.finish();
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^ (h >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bloom_filter() {
+        let filter = BloomFilter::with_estimated_keys(10000, 10);
+        for i in 0..10000u32 {
+            filter.insert(&i.to_le_bytes());
+        }
+        for i in 0..10000u32 {
+            assert!(filter.may_contain(&i.to_le_bytes()));
+        }
+        let false_positives = (10000..110000u32)
+            .filter(|i| filter.may_contain(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 2000, "false positives: {false_positives}");
+
+        filter.clear();
+        assert!(!filter.may_contain(&0u32.to_le_bytes()));
+    }
+
+    #[test]
+    fn bloom_filter_merge() {
+        let a = BloomFilter::with_estimated_keys(100, 10);
+        let b = BloomFilter::with_estimated_keys(100, 10);
+        a.insert(b"a");
+        b.insert(b"b");
+        assert!(!a.may_contain(b"b"));
+        a.merge(&b);
+        assert!(a.may_contain(b"a"));
+        assert!(a.may_contain(b"b"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Each power of two is split into `1 << SUB_BITS` buckets, so a bucket is at
+/// most 25% wider than its lower bound.
+const SUB_BITS: u32 = 2;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+/// Buckets from 1us to 2^28us (~268s), larger values fall in the last bucket.
+const NUM_BUCKETS: usize = 26 * SUB_BUCKETS as usize + SUB_BUCKETS as usize;
+
+/// A latency histogram with fixed exponential buckets in microseconds.
+///
+/// Recording is a single relaxed atomic increment, percentiles are only
+/// computed from a [`HistogramSnapshot`].
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; NUM_BUCKETS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let index = bucket_index(duration.as_micros() as u64);
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the records of `other` to this histogram.
+    pub fn merge(&self, other: &Histogram) {
+        for (bucket, other) in self.buckets.iter().zip(other.buckets.iter()) {
+            bucket.fetch_add(other.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect(),
+        }
+    }
+}
+
+/// A point-in-time copy of a [`Histogram`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    buckets: Vec<u64>,
+}
+
+impl HistogramSnapshot {
+    /// Returns the number of records.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Returns the upper bound of the bucket that contains the `p`-th
+    /// percentile, or zero if there is no record.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0 * count as f64).ceil() as u64).clamp(1, count);
+        let mut seen = 0;
+        for (index, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_micros(bucket_upper(index));
+            }
+        }
+        unreachable!()
+    }
+
+    /// Returns the number of records in each non-empty bucket, as
+    /// `(upper bound, count)` pairs.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(index, n)| (Duration::from_micros(bucket_upper(index)), *n))
+    }
+}
+
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let msb = 63 - micros.leading_zeros();
+    let sub = (micros >> (msb - SUB_BITS)) & (SUB_BUCKETS - 1);
+    let index = (msb - SUB_BITS + 1) as u64 * SUB_BUCKETS + sub;
+    (index as usize).min(NUM_BUCKETS - 1)
+}
+
+/// Returns the largest value of the bucket.
+fn bucket_upper(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let sub = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub + 1) << shift) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    #[test]
+    fn bucket_bounds() {
+        for micros in (0..100_000).chain([1 << 26, (1 << 27) - 1]) {
+            let index = bucket_index(micros);
+            assert!(micros <= bucket_upper(index), "{micros}");
+            assert!(index == 0 || micros > bucket_upper(index - 1), "{micros}");
+            // The bucket error is at most 25%.
+            assert!(bucket_upper(index) as f64 <= micros as f64 * 1.25 + 1.0);
+        }
+        assert_eq!(bucket_index(u64::MAX), NUM_BUCKETS - 1);
+        assert!(bucket_upper(NUM_BUCKETS - 1) >= 100_000_000);
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.snapshot().percentile(50.0), Duration::ZERO);
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 1000);
+        for (p, expect) in [(50.0, 500.0), (90.0, 900.0), (99.0, 990.0), (100.0, 1000.0)] {
+            let actual = snapshot.percentile(p).as_micros() as f64;
+            assert!(actual >= expect && actual <= expect * 1.25, "p{p}: {actual}");
+        }
+        assert_eq!(snapshot.buckets().map(|(_, n)| n).sum::<u64>(), 1000);
+    }
+
+    #[test]
+    fn concurrent_record_and_merge() {
+        let histogram = Arc::new(Histogram::new());
+        let handles: Vec<_> = (0..8u64)
+            .map(|i| {
+                let histogram = histogram.clone();
+                std::thread::spawn(move || {
+                    for j in 0..10000 {
+                        histogram.record(Duration::from_micros(i * 1000 + j));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(histogram.snapshot().count(), 80000);
+
+        let a = Histogram::new();
+        let b = Histogram::new();
+        let all = Histogram::new();
+        for micros in 0..5000u64 {
+            let d = Duration::from_micros(micros * micros);
+            if micros % 3 == 0 { &a } else { &b }.record(d);
+            all.record(d);
+        }
+        a.merge(&b);
+        assert_eq!(a.snapshot(), all.snapshot());
+    }
+
+    /// Measures the cost of `record` alone and with 8 threads recording into
+    /// the same histogram, run it with
+    /// `cargo test -p db --release bench_record -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_record() {
+        const N: u64 = 10_000_000;
+        let histogram = Arc::new(Histogram::new());
+        let start = std::time::Instant::now();
+        for i in 0..N {
+            histogram.record(std::hint::black_box(Duration::from_micros(i & 0xffff)));
+        }
+        println!("record: {:.1}ns/op", start.elapsed().as_nanos() as f64 / N as f64);
+
+        let start = std::time::Instant::now();
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let histogram = histogram.clone();
+                std::thread::spawn(move || {
+                    for i in 0..N / 8 {
+                        histogram.record(std::hint::black_box(Duration::from_micros(i & 0xffff)));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        println!("record, 8 threads: {:.1}ns/op", start.elapsed().as_nanos() as f64 / N as f64);
+        assert_eq!(histogram.snapshot().count(), 2 * N);
+    }
+}
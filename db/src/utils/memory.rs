@@ -0,0 +1,198 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// The subsystems that reserve memory from a [`MemoryController`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryUsage {
+    WriteBuffer = 0,
+    Cache = 1,
+    Readahead = 2,
+    Flush = 3,
+}
+
+const NUM_USAGES: usize = 4;
+
+/// Accounts the memory used by write buffers, the cache, readahead and
+/// in-flight flushes against a shared budget.
+///
+/// Every sized allocation reserves from the controller before allocating, the
+/// reservation is released when it is dropped. In strict mode a reservation
+/// that would exceed the budget fails with [`Error::MemoryLimit`] instead of
+/// overshooting.
+#[derive(Debug)]
+pub struct MemoryController {
+    budget: u64,
+    strict: bool,
+    used: AtomicU64,
+    usages: [AtomicU64; NUM_USAGES],
+}
+
+/// The memory reserved by each subsystem.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    pub write_buffer: u64,
+    pub cache: u64,
+    pub readahead: u64,
+    pub flush: u64,
+}
+
+impl MemoryController {
+    pub fn new(budget: u64, strict: bool) -> Self {
+        Self {
+            budget,
+            strict,
+            used: AtomicU64::new(0),
+            usages: Default::default(),
+        }
+    }
+
+    /// Reserves `size` bytes for `usage`.
+    ///
+    /// Returns [`Error::MemoryLimit`] if the controller is strict and the
+    /// reservation would exceed the budget.
+    pub fn reserve(self: &Arc<Self>, usage: MemoryUsage, size: u64) -> Result<MemoryReservation> {
+        if self.strict {
+            self.used
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                    used.checked_add(size).filter(|&used| used <= self.budget)
+                })
+                .map_err(|_| Error::MemoryLimit)?;
+        } else {
+            self.used.fetch_add(size, Ordering::AcqRel);
+        }
+        self.usages[usage as usize].fetch_add(size, Ordering::Relaxed);
+        Ok(MemoryReservation {
+            controller: self.clone(),
+            usage,
+            size,
+        })
+    }
+
+    /// Returns the budget in bytes.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Returns the total reserved bytes.
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Acquire)
+    }
+
+    /// Returns the reserved bytes of each subsystem.
+    pub fn stats(&self) -> MemoryStats {
+        let usage = |usage: MemoryUsage| self.usages[usage as usize].load(Ordering::Relaxed);
+        MemoryStats {
+            write_buffer: usage(MemoryUsage::WriteBuffer),
+            cache: usage(MemoryUsage::Cache),
+            readahead: usage(MemoryUsage::Readahead),
+            flush: usage(MemoryUsage::Flush),
+        }
+    }
+
+    fn release(&self, usage: MemoryUsage, size: u64) {
+        self.usages[usage as usize].fetch_sub(size, Ordering::Relaxed);
+        self.used.fetch_sub(size, Ordering::AcqRel);
+    }
+}
+
+/// Memory reserved from a [`MemoryController`], released when dropped.
+#[derive(Debug)]
+pub struct MemoryReservation {
+    controller: Arc<MemoryController>,
+    usage: MemoryUsage,
+    size: u64,
+}
+
+impl MemoryReservation {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn usage(&self) -> MemoryUsage {
+        self.usage
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.controller.release(self.usage, self.size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_memory_limit() {
+        let controller = Arc::new(MemoryController::new(100, true));
+        let buffer = controller.reserve(MemoryUsage::WriteBuffer, 60).unwrap();
+        let cache = controller.reserve(MemoryUsage::Cache, 40).unwrap();
+        assert!(matches!(
+            controller.reserve(MemoryUsage::Readahead, 1),
+            Err(Error::MemoryLimit)
+        ));
+        assert!(controller.reserve(MemoryUsage::Flush, u64::MAX).is_err());
+        assert_eq!(controller.used(), 100);
+        assert_eq!(
+            controller.stats(),
+            MemoryStats {
+                write_buffer: 60,
+                cache: 40,
+                ..Default::default()
+            }
+        );
+        drop(cache);
+        let readahead = controller.reserve(MemoryUsage::Readahead, 40).unwrap();
+        assert_eq!(readahead.size(), 40);
+        drop((buffer, readahead));
+        assert_eq!(controller.used(), 0);
+        assert_eq!(controller.stats(), MemoryStats::default());
+    }
+
+    #[test]
+    fn non_strict_memory_overshoots() {
+        let controller = Arc::new(MemoryController::new(10, false));
+        let a = controller.reserve(MemoryUsage::Cache, 8).unwrap();
+        let b = controller.reserve(MemoryUsage::Cache, 8).unwrap();
+        assert_eq!(controller.used(), 16);
+        drop((a, b));
+        assert_eq!(controller.used(), 0);
+    }
+
+    #[test]
+    fn memory_returns_to_baseline() {
+        let controller = Arc::new(MemoryController::new(1 << 10, true));
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let controller = controller.clone();
+                std::thread::spawn(move || {
+                    let usages = [
+                        MemoryUsage::WriteBuffer,
+                        MemoryUsage::Cache,
+                        MemoryUsage::Readahead,
+                        MemoryUsage::Flush,
+                    ];
+                    let mut held = Vec::new();
+                    for j in 0..1000u64 {
+                        let usage = usages[(i + j as usize) % usages.len()];
+                        if let Ok(reservation) = controller.reserve(usage, j % 64 + 1) {
+                            held.push(reservation);
+                        }
+                        assert!(controller.used() <= controller.budget());
+                        if held.len() > 4 {
+                            held.remove(0);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(controller.used(), 0);
+        assert_eq!(controller.stats(), MemoryStats::default());
+    }
+}
@@ -1 +1,7 @@
-pub mod atomic;
\ No newline at end of file
+pub mod atomic;
+pub mod bitmap;
+pub mod bloom;
+pub mod histogram;
+pub mod memory;
+pub mod rate_limiter;
+pub(crate) mod trace;
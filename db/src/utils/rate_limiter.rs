@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// The priority of a background IO request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// Flushes, which block writers if they fall behind.
+    High,
+    /// Compaction and space reclamation.
+    Low,
+}
+
+/// A token bucket shared by background jobs to limit their IO rate.
+///
+/// High priority requests are charged but never wait, low priority requests
+/// wait for the budget taken by everyone before them. So under pressure
+/// compaction yields the IO budget to flushes, and the total rate still
+/// converges to the limit.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    /// The time when all the bytes requested so far are paid off.
+    next_free: Mutex<Instant>,
+    total_bytes: AtomicU64,
+    wait_nanos: AtomicU64,
+}
+
+/// The statistics of a [`RateLimiter`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RateLimiterStats {
+    /// The number of bytes requested.
+    pub total_bytes: u64,
+    /// The total time low priority requests are throttled.
+    pub throttle_wait: Duration,
+}
+
+impl RateLimiter {
+    /// Creates a limiter of `bytes_per_sec`, zero means unlimited.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            next_free: Mutex::new(Instant::now()),
+            total_bytes: AtomicU64::new(0),
+            wait_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Requests to do `bytes` of IO, waits until the budget is available.
+    pub async fn request(&self, priority: IoPriority, bytes: u64) {
+        self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+        if self.bytes_per_sec == 0 {
+            return;
+        }
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let now = Instant::now();
+        let start = {
+            let mut next_free = self.next_free.lock().unwrap_or_else(|err| err.into_inner());
+            let start = (*next_free).max(now);
+            *next_free = start + cost;
+            start
+        };
+        if priority == IoPriority::Low && start > now {
+            tokio::time::sleep_until(start).await;
+            let waited = start - now;
+            self.wait_nanos.fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the limit in bytes per second, zero means unlimited.
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    pub fn stats(&self) -> RateLimiterStats {
+        RateLimiterStats {
+            total_bytes: self.total_bytes.load(Ordering::Relaxed),
+            throttle_wait: Duration::from_nanos(self.wait_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    #[tokio::test]
+    async fn unlimited() {
+        let limiter = RateLimiter::new(0);
+        for _ in 0..100 {
+            limiter.request(IoPriority::Low, 1 << 30).await;
+        }
+        assert_eq!(limiter.stats().total_bytes, 100 << 30);
+        assert_eq!(limiter.stats().throttle_wait, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn rate_stays_under_limit() {
+        const RATE: u64 = 1 << 20;
+        const CHUNK: u64 = 32 << 10;
+        let limiter = Arc::new(RateLimiter::new(RATE));
+        let start = Instant::now();
+        let mut tasks = Vec::new();
+        for _ in 0..4 {
+            let limiter = limiter.clone();
+            tasks.push(tokio::spawn(async move {
+                for _ in 0..4 {
+                    limiter.request(IoPriority::Low, CHUNK).await;
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // The first chunk is free, the others take half a second in total.
+        let elapsed = start.elapsed();
+        let max_bytes = RATE as f64 * elapsed.as_secs_f64() + CHUNK as f64;
+        assert!((16 * CHUNK) as f64 <= max_bytes, "too fast: {elapsed:?}");
+        assert!(elapsed >= Duration::from_millis(450));
+        assert!(limiter.stats().throttle_wait > Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn high_priority_does_not_wait() {
+        let limiter = RateLimiter::new(8 << 20);
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.request(IoPriority::High, 1 << 20).await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert_eq!(limiter.stats().throttle_wait, Duration::ZERO);
+        // The low priority job pays for the flushes.
+        let start = Instant::now();
+        limiter.request(IoPriority::Low, 1).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+        assert!(limiter.stats().throttle_wait >= Duration::from_millis(400));
+    }
+}